@@ -0,0 +1,895 @@
+use crate::SerpicoError;
+use num_bigint::BigUint;
+use serde_json::Value;
+use starknet::core::types::{BlockId, BlockTag, ContractClass, Felt};
+use starknet::core::utils::starknet_keccak;
+use starknet::providers::jsonrpc::HttpTransport;
+use starknet::providers::{JsonRpcClient, Provider};
+use std::collections::{HashMap, HashSet};
+use std::fs::read_to_string;
+use std::iter::repeat;
+
+/// Longest array or byte array accepted while decoding, longer ones are treated as garbage
+const MAX_DECODED_LEN: u64 = 100_000;
+
+/// Type of a decoded column
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ValueKind {
+    Text,
+    Integer,
+    Boolean,
+    List,
+}
+
+/// Cairo value decoded from felts
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum AbiValue {
+    Text(String),
+    Integer(u64),
+    Boolean(bool),
+    List(Vec<String>),
+    Null,
+}
+
+impl AbiValue {
+    pub fn kind(&self) -> ValueKind {
+        match self {
+            AbiValue::Text(_) | AbiValue::Null => ValueKind::Text,
+            AbiValue::Integer(_) => ValueKind::Integer,
+            AbiValue::Boolean(_) => ValueKind::Boolean,
+            AbiValue::List(_) => ValueKind::List,
+        }
+    }
+
+    pub fn to_text(&self) -> Option<String> {
+        match self {
+            AbiValue::Text(text) => Some(text.clone()),
+            AbiValue::Integer(value) => Some(value.to_string()),
+            AbiValue::Boolean(value) => Some(value.to_string()),
+            AbiValue::List(values) => Some(format!("[{}]", values.join(","))),
+            AbiValue::Null => None,
+        }
+    }
+}
+
+/// Named and typed value of a struct, event or function
+#[derive(Debug, Clone)]
+struct Member {
+    name: String,
+    ty: String,
+}
+
+/// Event definition found in an ABI
+#[derive(Debug, Clone)]
+pub struct EventAbi {
+    /// Short name of the event, e.g. `Transfer`
+    pub name: String,
+    /// Decoded columns, flattened for structs, tuples and enums
+    pub columns: Vec<(String, ValueKind)>,
+    // full type path of the event
+    path: String,
+    // keys identifying the event: its selector, preceded by the selectors of nested enums
+    selector_keys: Vec<Felt>,
+    // members in declaration order, and whether they are read from keys or data
+    members: Vec<(Member, bool)>,
+}
+
+impl EventAbi {
+    /// Hash of the event definition, which changes with its path, keys, members or columns
+    pub fn definition_hash(&self) -> Felt {
+        let mut definition = format!("{}|", self.path);
+        for key in self.selector_keys.iter() {
+            definition.push_str(&format!("{:x},", key));
+        }
+        for (member, is_key) in self.members.iter() {
+            definition.push_str(&format!("|{}:{}:{}", member.name, member.ty, is_key));
+        }
+        for (name, kind) in self.columns.iter() {
+            definition.push_str(&format!("|{}:{:?}", name, kind));
+        }
+        starknet_keccak(definition.as_bytes())
+    }
+}
+
+/// Decodes values of a contract class from its ABI, either Cairo 0 (legacy) or Cairo 1 (Sierra)
+#[derive(Debug, Clone)]
+pub struct AbiDecoder {
+    events: Vec<EventAbi>,
+    // functions and L1 handlers by selector, with their inputs
+    functions: HashMap<Felt, (String, Vec<Member>)>,
+    structs: HashMap<String, Vec<Member>>,
+    enums: HashMap<String, Vec<Member>>,
+}
+
+impl AbiDecoder {
+    pub fn new(abi: &Value) -> Result<Self, SerpicoError> {
+        let entries = abi
+            .as_array()
+            .ok_or_else(|| SerpicoError::AbiErr("ABI should be a JSON array".to_string()))?;
+
+        let mut decoder = AbiDecoder {
+            events: Vec::new(),
+            functions: HashMap::new(),
+            structs: HashMap::new(),
+            enums: HashMap::new(),
+        };
+        // Cairo 1 events are structs wrapped in enums, keyed by their type name
+        let mut event_structs = HashMap::new();
+        let mut event_enums = HashMap::new();
+        let mut legacy_events = Vec::new();
+
+        // Cairo 1 functions can be grouped in interfaces
+        let interface_items = entries
+            .iter()
+            .filter(|entry| entry["type"].as_str() == Some("interface"))
+            .filter_map(|entry| entry["items"].as_array())
+            .flatten();
+        for entry in entries.iter().chain(interface_items) {
+            let name = entry["name"].as_str().unwrap_or_default().to_string();
+            match (entry["type"].as_str(), entry["kind"].as_str()) {
+                (Some("function" | "l1_handler"), _) => {
+                    decoder
+                        .functions
+                        .insert(selector(&name), (name, members(&entry["inputs"])));
+                }
+                (Some("struct"), _) => {
+                    decoder.structs.insert(name, members(&entry["members"]));
+                }
+                (Some("enum"), _) => {
+                    decoder.enums.insert(name, members(&entry["variants"]));
+                }
+                (Some("event"), Some("struct")) => {
+                    event_structs.insert(name, entry["members"].clone());
+                }
+                (Some("event"), Some("enum")) => {
+                    event_enums.insert(name, entry["variants"].clone());
+                }
+                (Some("event"), _) => legacy_events.push(entry),
+                _ => (),
+            }
+        }
+
+        // events are decoded once every struct is known
+        for entry in legacy_events {
+            let name = entry["name"].as_str().unwrap_or_default().to_string();
+            let mut event_members: Vec<(Member, bool)> = members(&entry["keys"])
+                .into_iter()
+                .map(|member| (member, true))
+                .collect();
+            event_members.extend(
+                members(&entry["data"])
+                    .into_iter()
+                    .map(|member| (member, false)),
+            );
+            decoder.push_event(
+                name.clone(),
+                name.clone(),
+                vec![selector(&name)],
+                event_members,
+            );
+        }
+
+        // root enums are the ones no other event enum refers to
+        let nested: HashSet<String> = event_enums
+            .values()
+            .flat_map(members)
+            .map(|variant| variant.ty)
+            .collect();
+        let mut roots: Vec<&String> = event_enums
+            .keys()
+            .filter(|name| !nested.contains(*name))
+            .collect();
+        roots.sort();
+        for root in roots {
+            decoder.push_enum_events(root, Vec::new(), &event_structs, &event_enums);
+        }
+
+        // events sharing a short name across components keep their full path
+        let mut names = HashMap::new();
+        for event in decoder.events.iter() {
+            *names.entry(event.name.clone()).or_insert(0) += 1;
+        }
+        for event in decoder.events.iter_mut() {
+            if names[&event.name] > 1 {
+                event.name = event.path.replace("::", "_");
+            }
+        }
+
+        Ok(decoder)
+    }
+
+    pub fn events(&self) -> &[EventAbi] {
+        &self.events
+    }
+
+    /// First key of every event, which is enough for the node to filter them
+    pub fn event_selectors(&self) -> Vec<Felt> {
+        self.events
+            .iter()
+            .filter_map(|event| event.selector_keys.first().copied())
+            .collect()
+    }
+
+    /// Name of the function called with `selector` and its decoded arguments as `name=value`,
+    /// arguments are `None` when the calldata does not match the function inputs
+    pub fn decode_call(
+        &self,
+        selector: Felt,
+        calldata: &[Felt],
+    ) -> Option<(String, Option<Vec<String>>)> {
+        let (name, inputs) = self.functions.get(&selector)?;
+        let inputs: Vec<(Member, bool)> =
+            inputs.iter().map(|input| (input.clone(), false)).collect();
+        let mut felts = calldata.iter().copied();
+        let arguments = self
+            .decode_members(&inputs, &mut std::iter::empty(), &mut felts)
+            .filter(|_| felts.next().is_none())
+            .map(|values| {
+                values
+                    .into_iter()
+                    .map(|(name, value)| {
+                        format!(
+                            "{}={}",
+                            name,
+                            value.to_text().unwrap_or_else(|| "null".to_string())
+                        )
+                    })
+                    .collect()
+            });
+        Some((name.clone(), arguments))
+    }
+
+    /// Index of the event matching `keys` with its decoded values, following `EventAbi::columns`
+    pub fn decode_event(&self, keys: &[Felt], data: &[Felt]) -> Option<(usize, Vec<AbiValue>)> {
+        let (index, event) = self
+            .events
+            .iter()
+            .enumerate()
+            .filter(|(_, event)| keys.starts_with(&event.selector_keys))
+            .max_by_key(|(_, event)| event.selector_keys.len())?;
+
+        let mut keys = keys[event.selector_keys.len()..].iter().copied();
+        let mut data = data.iter().copied();
+        let values = self.decode_members(&event.members, &mut keys, &mut data)?;
+        // leftover felts mean the event was emitted with another definition
+        if keys.next().is_some() || data.next().is_some() {
+            return None;
+        }
+        Some((index, values.into_iter().map(|(_, value)| value).collect()))
+    }
+
+    fn push_event(
+        &mut self,
+        name: String,
+        path: String,
+        selector_keys: Vec<Felt>,
+        members: Vec<(Member, bool)>,
+    ) {
+        // decoding zeros gives empty arrays and first enum variants, hence every column
+        let columns = self
+            .decode_members(&members, &mut repeat(Felt::ZERO), &mut repeat(Felt::ZERO))
+            .unwrap_or_default()
+            .into_iter()
+            .map(|(name, value)| (name, value.kind()))
+            .collect();
+        self.events.push(EventAbi {
+            name,
+            columns,
+            path,
+            selector_keys,
+            members,
+        });
+    }
+
+    fn push_enum_events(
+        &mut self,
+        enum_name: &str,
+        selector_keys: Vec<Felt>,
+        event_structs: &HashMap<String, Value>,
+        event_enums: &HashMap<String, Value>,
+    ) {
+        let variants = match event_enums.get(enum_name) {
+            Some(variants) => variants.as_array().cloned().unwrap_or_default(),
+            None => return,
+        };
+        for variant in variants {
+            let name = variant["name"].as_str().unwrap_or_default();
+            let ty = variant["type"].as_str().unwrap_or_default();
+            let flat = variant["kind"].as_str() == Some("flat");
+
+            if let Some(struct_members) = event_structs.get(ty) {
+                let mut keys = selector_keys.clone();
+                keys.push(selector(name));
+                let struct_members = struct_members
+                    .as_array()
+                    .cloned()
+                    .unwrap_or_default()
+                    .iter()
+                    .map(|member| {
+                        (
+                            Member {
+                                name: member["name"].as_str().unwrap_or_default().to_string(),
+                                ty: member["type"].as_str().unwrap_or_default().to_string(),
+                            },
+                            member["kind"].as_str() == Some("key"),
+                        )
+                    })
+                    .collect();
+                self.push_event(name.to_string(), ty.to_string(), keys, struct_members);
+            } else if event_enums.contains_key(ty) {
+                // flat variants do not add their own selector to the keys
+                let mut keys = selector_keys.clone();
+                if !flat {
+                    keys.push(selector(name));
+                }
+                self.push_enum_events(ty, keys, event_structs, event_enums);
+            }
+        }
+    }
+
+    /// Decodes members in order, reading each of them from keys or data
+    fn decode_members(
+        &self,
+        members: &[(Member, bool)],
+        keys: &mut dyn Iterator<Item = Felt>,
+        data: &mut dyn Iterator<Item = Felt>,
+    ) -> Option<Vec<(String, AbiValue)>> {
+        let mut values = Vec::new();
+        // legacy arrays `x: felt*` are preceded by their length `x_len`
+        let mut lengths = HashMap::new();
+        for (member, from_keys) in members {
+            let felts: &mut dyn Iterator<Item = Felt> =
+                if *from_keys { &mut *keys } else { &mut *data };
+            if let Some(element_ty) = member.ty.strip_suffix('*') {
+                let len = lengths.get(&format!("{}_len", member.name)).copied()?;
+                values.push((
+                    member.name.clone(),
+                    self.decode_array(element_ty, len, felts)?,
+                ));
+                continue;
+            }
+            self.decode_value(&member.ty, &member.name, felts, &mut values)?;
+            if let (true, Some((_, AbiValue::Text(text)))) =
+                (member.name.ends_with("_len"), values.last())
+            {
+                if let Ok(Ok(len)) = Felt::from_hex(text).map(u64::try_from) {
+                    lengths.insert(member.name.clone(), len);
+                }
+            }
+        }
+        Some(values)
+    }
+
+    /// Decodes a value of type `ty` into one or several flattened columns
+    fn decode_value(
+        &self,
+        ty: &str,
+        name: &str,
+        felts: &mut dyn Iterator<Item = Felt>,
+        values: &mut Vec<(String, AbiValue)>,
+    ) -> Option<()> {
+        let value = match ty {
+            "felt"
+            | "core::felt252"
+            | "core::starknet::contract_address::ContractAddress"
+            | "core::starknet::class_hash::ClassHash"
+            | "core::starknet::eth_address::EthAddress"
+            | "core::starknet::storage_access::StorageAddress"
+            | "core::bytes_31::bytes31" => AbiValue::Text(format!("0x{:x}", felts.next()?)),
+            "core::integer::u8"
+            | "core::integer::u16"
+            | "core::integer::u32"
+            | "core::integer::u64"
+            | "core::integer::usize" => AbiValue::Integer(u64::try_from(felts.next()?).ok()?),
+            "core::integer::u128" => AbiValue::Text(felts.next()?.to_string()),
+            "core::integer::i8"
+            | "core::integer::i16"
+            | "core::integer::i32"
+            | "core::integer::i64"
+            | "core::integer::i128" => {
+                let felt = felts.next()?;
+                match u128::try_from(felt) {
+                    Ok(value) => AbiValue::Text(value.to_string()),
+                    Err(_) => AbiValue::Text(format!("-{}", Felt::ZERO - felt)),
+                }
+            }
+            "Uint256" | "core::integer::u256" => {
+                let (low, high) = (felts.next()?, felts.next()?);
+                AbiValue::Text(u256_to_decimal(low, high))
+            }
+            "core::bool" => AbiValue::Boolean(felts.next()? != Felt::ZERO),
+            "core::byte_array::ByteArray" => AbiValue::Text(decode_byte_array(felts)?),
+            _ => {
+                if let Some(element_ty) = array_element(ty) {
+                    let len = u64::try_from(felts.next()?).ok()?;
+                    values.push((name.to_string(), self.decode_array(element_ty, len, felts)?));
+                } else if let Some(tuple_tys) = tuple_elements(ty) {
+                    for (i, element_ty) in tuple_tys.iter().enumerate() {
+                        self.decode_value(element_ty, &format!("{}_{}", name, i), felts, values)?;
+                    }
+                } else if let Some(members) = self.structs.get(ty) {
+                    for member in members {
+                        self.decode_value(
+                            &member.ty,
+                            &format!("{}_{}", name, member.name),
+                            felts,
+                            values,
+                        )?;
+                    }
+                } else if let Some(variants) = self.enums.get(ty) {
+                    let index = u64::try_from(felts.next()?).ok()? as usize;
+                    let variant = variants.get(index)?;
+                    let payload = match variant.ty.as_str() {
+                        "()" => AbiValue::Null,
+                        variant_ty => AbiValue::Text(self.decode_text(variant_ty, felts)?),
+                    };
+                    values.push((name.to_string(), AbiValue::Text(variant.name.clone())));
+                    values.push((format!("{}_value", name), payload));
+                } else {
+                    // unknown types are assumed to fit in a single felt
+                    values.push((
+                        name.to_string(),
+                        AbiValue::Text(format!("0x{:x}", felts.next()?)),
+                    ));
+                }
+                return Some(());
+            }
+        };
+        values.push((name.to_string(), value));
+        Some(())
+    }
+
+    fn decode_array(
+        &self,
+        element_ty: &str,
+        len: u64,
+        felts: &mut dyn Iterator<Item = Felt>,
+    ) -> Option<AbiValue> {
+        if len > MAX_DECODED_LEN {
+            return None;
+        }
+        let mut elements = Vec::new();
+        for _ in 0..len {
+            elements.push(self.decode_text(element_ty, felts)?);
+        }
+        Some(AbiValue::List(elements))
+    }
+
+    /// Decodes a value into a single string, e.g. `(0x1,0x2)` for a struct
+    fn decode_text(&self, ty: &str, felts: &mut dyn Iterator<Item = Felt>) -> Option<String> {
+        let mut values = Vec::new();
+        self.decode_value(ty, "", felts, &mut values)?;
+        let texts: Vec<String> = values
+            .iter()
+            .map(|(_, value)| value.to_text().unwrap_or_else(|| "null".to_string()))
+            .collect();
+        if texts.len() == 1 {
+            Some(texts[0].clone())
+        } else {
+            Some(format!("({})", texts.join(",")))
+        }
+    }
+}
+
+/// Loads an ABI from a local JSON file (either the ABI itself or a whole class),
+/// or from the class of `contract` on chain
+pub async fn load_abi(
+    client: &JsonRpcClient<HttpTransport>,
+    abi_file: Option<&str>,
+    contract: Option<Felt>,
+) -> Result<Value, SerpicoError> {
+    let abi = match (abi_file, contract) {
+        (Some(abi_file), _) => {
+            let content = read_to_string(abi_file).map_err(SerpicoError::IoErr)?;
+            let json: Value = serde_json::from_str(&content)
+                .map_err(|e| SerpicoError::AbiErr(format!("{}: {}", abi_file, e)))?;
+            match json.get("abi") {
+                Some(abi) => abi.clone(),
+                None => json,
+            }
+        }
+        (None, Some(contract)) => {
+            match client
+                .get_class_at(BlockId::Tag(BlockTag::Latest), contract)
+                .await
+                .map_err(SerpicoError::ClientErr)?
+            {
+                ContractClass::Sierra(class) => Value::String(class.abi),
+                ContractClass::Legacy(class) => serde_json::to_value(class.abi.unwrap_or_default())
+                    .map_err(|e| SerpicoError::AbiErr(e.to_string()))?,
+            }
+        }
+        (None, None) => {
+            return Err(SerpicoError::AbiErr(
+                "decoding needs --abi or --contract".to_string(),
+            ))
+        }
+    };
+
+    // Sierra classes store their ABI as a JSON string
+    match abi {
+        Value::String(abi) => {
+            serde_json::from_str(&abi).map_err(|e| SerpicoError::AbiErr(e.to_string()))
+        }
+        abi => Ok(abi),
+    }
+}
+
+/// Call of an account `__execute__`, as (contract, selector, calldata)
+pub type ExecuteCall = (Felt, Felt, Vec<Felt>);
+
+/// Calls packed in the calldata of an account `__execute__`, as (layout, calls).
+///
+/// Cairo 0 accounts send a call array of (to, selector, data_offset, data_len) followed by the
+/// concatenated calldata, Cairo 1 accounts send each call with its own calldata. Calldata
+/// matching both layouts is read as Cairo 1, the layout of current accounts, and calldata
+/// matching neither exactly gives `None`.
+pub fn parse_execute_calldata(calldata: &[Felt]) -> Option<(&'static str, Vec<ExecuteCall>)> {
+    parse_cairo1_calls(calldata)
+        .map(|calls| ("cairo1", calls))
+        .or_else(|| parse_cairo0_calls(calldata).map(|calls| ("cairo0", calls)))
+}
+
+fn parse_cairo1_calls(calldata: &[Felt]) -> Option<Vec<ExecuteCall>> {
+    let (calls_len, mut rest) = calldata.split_first()?;
+    let calls_len = u64::try_from(*calls_len).ok()?;
+    if calls_len > rest.len() as u64 {
+        return None;
+    }
+
+    let mut calls = Vec::new();
+    for _ in 0..calls_len {
+        let [to, selector, data_len, tail @ ..] = rest else {
+            return None;
+        };
+        let data_len = u64::try_from(*data_len).ok()?;
+        if data_len > tail.len() as u64 {
+            return None;
+        }
+        let (data, tail) = tail.split_at(data_len as usize);
+        calls.push((*to, *selector, data.to_vec()));
+        rest = tail;
+    }
+    if rest.is_empty() {
+        Some(calls)
+    } else {
+        None
+    }
+}
+
+fn parse_cairo0_calls(calldata: &[Felt]) -> Option<Vec<ExecuteCall>> {
+    let (calls_len, rest) = calldata.split_first()?;
+    let calls_len = u64::try_from(*calls_len).ok()?;
+    if calls_len > rest.len() as u64 / 4 {
+        return None;
+    }
+    let (call_array, rest) = rest.split_at(calls_len as usize * 4);
+    let (data_len, data) = rest.split_first()?;
+    if u64::try_from(*data_len).ok()? != data.len() as u64 {
+        return None;
+    }
+
+    let mut calls = Vec::new();
+    for call in call_array.chunks(4) {
+        let offset = u64::try_from(call[2]).ok()?;
+        let len = u64::try_from(call[3]).ok()?;
+        if offset.checked_add(len)? > data.len() as u64 {
+            return None;
+        }
+        calls.push((
+            call[0],
+            call[1],
+            data[offset as usize..(offset + len) as usize].to_vec(),
+        ));
+    }
+    Some(calls)
+}
+
+fn members(entries: &Value) -> Vec<Member> {
+    entries
+        .as_array()
+        .map(|entries| {
+            entries
+                .iter()
+                .map(|entry| Member {
+                    name: entry["name"].as_str().unwrap_or_default().to_string(),
+                    ty: entry["type"].as_str().unwrap_or_default().to_string(),
+                })
+                .collect()
+        })
+        .unwrap_or_default()
+}
+
+fn selector(name: &str) -> Felt {
+    starknet_keccak(name.as_bytes())
+}
+
+/// Element type of `core::array::Array::<T>` and `core::array::Span::<T>`
+fn array_element(ty: &str) -> Option<&str> {
+    ty.strip_prefix("core::array::Array::<")
+        .or_else(|| ty.strip_prefix("core::array::Span::<"))
+        .and_then(|ty| ty.strip_suffix('>'))
+}
+
+/// Element types of a tuple such as `(core::felt252, core::integer::u8)`
+fn tuple_elements(ty: &str) -> Option<Vec<String>> {
+    let inner = ty.strip_prefix('(')?.strip_suffix(')')?;
+    if inner.is_empty() {
+        return None;
+    }
+
+    let mut elements = Vec::new();
+    let (mut depth, mut start) = (0, 0);
+    for (i, c) in inner.char_indices() {
+        match c {
+            '(' | '<' => depth += 1,
+            ')' | '>' => depth -= 1,
+            ',' if depth == 0 => {
+                elements.push(inner[start..i].trim().to_string());
+                start = i + 1;
+            }
+            _ => (),
+        }
+    }
+    elements.push(inner[start..].trim().to_string());
+    Some(elements)
+}
+
+/// Decimal value of a u256 split into low and high u128 felts
+pub fn u256_to_decimal(low: Felt, high: Felt) -> String {
+    let low = BigUint::from_bytes_be(&low.to_bytes_be());
+    let high = BigUint::from_bytes_be(&high.to_bytes_be());
+    ((high << 128u32) + low).to_string()
+}
+
+/// Decodes a Cairo 1 `ByteArray`: full 31 bytes words, then a pending word and its length
+fn decode_byte_array(felts: &mut dyn Iterator<Item = Felt>) -> Option<String> {
+    let words = u64::try_from(felts.next()?).ok()?;
+    if words > MAX_DECODED_LEN {
+        return None;
+    }
+    let mut bytes = Vec::new();
+    for _ in 0..words {
+        bytes.extend_from_slice(&felts.next()?.to_bytes_be()[1..]);
+    }
+    let pending_word = felts.next()?.to_bytes_be();
+    let pending_len = u64::try_from(felts.next()?).ok()? as usize;
+    if pending_len > 31 {
+        return None;
+    }
+    bytes.extend_from_slice(&pending_word[32 - pending_len..]);
+    Some(String::from_utf8_lossy(&bytes).to_string())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use serde_json::json;
+
+    fn decoder() -> AbiDecoder {
+        AbiDecoder::new(&json!([
+            {
+                "type": "enum",
+                "name": "token::Status",
+                "variants": [
+                    {"name": "Active", "type": "()"},
+                    {"name": "Paused", "type": "core::felt252"}
+                ]
+            },
+            {
+                "type": "event",
+                "name": "token::Transfer",
+                "kind": "struct",
+                "members": [
+                    {"name": "from", "type": "core::starknet::contract_address::ContractAddress", "kind": "key"},
+                    {"name": "value", "type": "core::integer::u256", "kind": "data"}
+                ]
+            },
+            {
+                "type": "event",
+                "name": "token::Update",
+                "kind": "struct",
+                "members": [
+                    {"name": "name", "type": "core::byte_array::ByteArray", "kind": "data"},
+                    {"name": "delta", "type": "core::integer::i32", "kind": "data"},
+                    {"name": "status", "type": "token::Status", "kind": "data"}
+                ]
+            },
+            {
+                "type": "event",
+                "name": "token::Event",
+                "kind": "enum",
+                "variants": [
+                    {"name": "Transfer", "type": "token::Transfer", "kind": "nested"},
+                    {"name": "Update", "type": "token::Update", "kind": "nested"}
+                ]
+            }
+        ]))
+        .unwrap()
+    }
+
+    fn text(value: &str) -> AbiValue {
+        AbiValue::Text(value.to_string())
+    }
+
+    #[test]
+    fn decodes_u256() {
+        let (index, values) = decoder()
+            .decode_event(
+                &[selector("Transfer"), Felt::from(0x123u64)],
+                &[Felt::ONE, Felt::ONE],
+            )
+            .unwrap();
+        assert_eq!(decoder().events()[index].name, "Transfer");
+        assert_eq!(
+            values,
+            vec![
+                text("0x123"),
+                text("340282366920938463463374607431768211457")
+            ]
+        );
+    }
+
+    #[test]
+    fn decodes_byte_array_signed_int_and_enum() {
+        let data = [
+            // "hello": no full word, then the pending word and its length
+            Felt::ZERO,
+            Felt::from(0x68656c6c6fu64),
+            Felt::from(5u64),
+            Felt::ZERO - Felt::from(5u64),
+            // second variant with its payload
+            Felt::ONE,
+            Felt::from(0x2au64),
+        ];
+        let (index, values) = decoder()
+            .decode_event(&[selector("Update")], &data)
+            .unwrap();
+        assert_eq!(decoder().events()[index].name, "Update");
+        assert_eq!(
+            values,
+            vec![text("hello"), text("-5"), text("Paused"), text("0x2a")]
+        );
+        assert_eq!(
+            decoder().events()[index]
+                .columns
+                .iter()
+                .map(|(name, _)| name.as_str())
+                .collect::<Vec<_>>(),
+            vec!["name", "delta", "status", "status_value"]
+        );
+    }
+
+    #[test]
+    fn decodes_unit_enum_variant_as_null() {
+        let data = [
+            Felt::ZERO,
+            Felt::ZERO,
+            Felt::ZERO,
+            Felt::from(7u64),
+            Felt::ZERO,
+        ];
+        let (_, values) = decoder()
+            .decode_event(&[selector("Update")], &data)
+            .unwrap();
+        assert_eq!(
+            values,
+            vec![text(""), text("7"), text("Active"), AbiValue::Null]
+        );
+    }
+
+    #[test]
+    fn rejects_truncated_data() {
+        assert_eq!(
+            decoder().decode_event(&[selector("Transfer"), Felt::ONE], &[Felt::ONE]),
+            None
+        );
+    }
+
+    #[test]
+    fn rejects_leftover_keys_and_data() {
+        // an ERC-721 like transfer keeps its token id in keys
+        assert_eq!(
+            decoder().decode_event(
+                &[selector("Transfer"), Felt::ONE, Felt::TWO],
+                &[Felt::ONE, Felt::ONE]
+            ),
+            None
+        );
+        assert_eq!(
+            decoder().decode_event(
+                &[selector("Transfer"), Felt::ONE],
+                &[Felt::ONE, Felt::ONE, Felt::ONE]
+            ),
+            None
+        );
+    }
+
+    #[test]
+    fn hashes_event_definitions() {
+        let other = AbiDecoder::new(&json!([
+            {
+                "type": "event",
+                "name": "token::Transfer",
+                "kind": "struct",
+                "members": [
+                    {"name": "from", "type": "core::starknet::contract_address::ContractAddress", "kind": "data"},
+                    {"name": "value", "type": "core::integer::u256", "kind": "data"}
+                ]
+            },
+            {
+                "type": "event",
+                "name": "token::Event",
+                "kind": "enum",
+                "variants": [
+                    {"name": "Transfer", "type": "token::Transfer", "kind": "nested"}
+                ]
+            }
+        ]))
+        .unwrap();
+        let decoder = decoder();
+        let transfer = &decoder.events()[0];
+        assert_eq!(transfer.name, "Transfer");
+        assert_eq!(
+            transfer.definition_hash(),
+            decoder.events()[0].definition_hash()
+        );
+        assert_ne!(
+            transfer.definition_hash(),
+            other.events()[0].definition_hash()
+        );
+        assert_ne!(
+            transfer.definition_hash(),
+            decoder.events()[1].definition_hash()
+        );
+    }
+
+    fn felts(values: &[u64]) -> Vec<Felt> {
+        values.iter().copied().map(Felt::from).collect()
+    }
+
+    #[test]
+    fn parses_execute_calldata_layouts() {
+        // Cairo 1: (to, selector, calldata) per call
+        assert_eq!(
+            parse_execute_calldata(&felts(&[1, 0xa, 0xb, 2, 7, 8])),
+            Some((
+                "cairo1",
+                vec![(Felt::from(0xau64), Felt::from(0xbu64), felts(&[7, 8]))]
+            ))
+        );
+        // Cairo 0: call array then the concatenated calldata
+        assert_eq!(
+            parse_execute_calldata(&felts(&[1, 0xa, 0xb, 0, 2, 2, 7, 8])),
+            Some((
+                "cairo0",
+                vec![(Felt::from(0xau64), Felt::from(0xbu64), felts(&[7, 8]))]
+            ))
+        );
+        assert_eq!(parse_execute_calldata(&felts(&[2, 0xa, 0xb, 1])), None);
+        assert_eq!(parse_execute_calldata(&[]), None);
+    }
+
+    #[test]
+    fn reads_ambiguous_execute_calldata_as_cairo_1() {
+        let calldata = felts(&[2, 0xa, 0xb, 0, 1, 0xc, 4, 0, 1, 1, 0xd]);
+        // valid Cairo 0 calldata too, where both calls get [0xd]
+        assert_eq!(
+            parse_cairo0_calls(&calldata),
+            Some(vec![
+                (Felt::from(0xau64), Felt::from(0xbu64), felts(&[0xd])),
+                (Felt::from(0xcu64), Felt::from(4u64), felts(&[0xd])),
+            ])
+        );
+        assert_eq!(
+            parse_execute_calldata(&calldata),
+            Some((
+                "cairo1",
+                vec![
+                    (Felt::from(0xau64), Felt::from(0xbu64), vec![]),
+                    (Felt::ONE, Felt::from(0xcu64), felts(&[0, 1, 1, 0xd])),
+                ]
+            ))
+        );
+    }
+}
@@ -1,17 +1,649 @@
-use crate::SerpicoError;
+use crate::{Datasets, SerpicoError};
+use chrono::{DateTime, NaiveDate};
+use starknet::core::types::Felt;
+use starknet::core::utils::get_storage_var_address;
+use std::fs::read_to_string;
 
-pub fn parse_blocks(block_str: String, last_block: u64) -> Result<(u64, u64), SerpicoError> {
-    let block_parts: Vec<&str> = block_str.split(':').collect();
-    let block_start = match block_parts[0] {
-        "" => 1,
-        x => x.parse::<u64>().map_err(SerpicoError::IntParsingErr)?,
+/// Block used when the start of a range is left empty
+pub const DEFAULT_START_BLOCK: u64 = 1;
+
+/// Block interval requested on the command line, both bounds included.
+///
+/// Only one block every `step` blocks (counted from `start`) is fetched.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct BlockRange {
+    pub start: u64,
+    pub end: u64,
+    pub step: u64,
+}
+
+impl BlockRange {
+    /// Remainder of the sampled blocks modulo `step`, ranges with the same step and offset
+    /// sample the same blocks
+    pub fn offset(&self) -> u64 {
+        self.start % self.step
+    }
+
+    /// First sampled block greater or equal to `block`
+    pub fn first_block_from(&self, block: u64) -> u64 {
+        if block <= self.start {
+            return self.start;
+        }
+        let offset = (block - self.start) % self.step;
+        if offset == 0 {
+            block
+        } else {
+            block + (self.step - offset)
+        }
+    }
+}
+
+/// Parses a comma separated list of datasets such as `blocks,transactions,logs`
+pub fn parse_datasets(dataset_str: String) -> Result<Vec<Datasets>, SerpicoError> {
+    let mut datasets = Vec::new();
+    for name in dataset_str.split(',') {
+        let dataset = Datasets::from_name(name.trim()).ok_or_else(|| {
+            SerpicoError::DatasetParsingErr(format!(
+                "unknown dataset `{}`, valid datasets are {}",
+                name,
+                Datasets::ALL
+                    .iter()
+                    .map(|dataset| dataset.to_name())
+                    .collect::<Vec<_>>()
+                    .join(", ")
+            ))
+        })?;
+        if !datasets.contains(&dataset) {
+            datasets.push(dataset);
+        }
+    }
+    Ok(datasets)
+}
+
+/// Parses a hex contract address such as `0x049d...`
+pub fn parse_address(address_str: String) -> Result<Felt, SerpicoError> {
+    Felt::from_hex(address_str.trim()).map_err(|_| {
+        SerpicoError::AddressParsingErr(format!("`{}` is not a valid address", address_str))
+    })
+}
+
+/// Parses a `<token>:<holder>` call target
+pub fn parse_address_pair(target: &str) -> Result<(Felt, Felt), SerpicoError> {
+    match target.split_once(':') {
+        Some((first, second)) => Ok((
+            parse_address(first.to_string())?,
+            parse_address(second.to_string())?,
+        )),
+        None => Err(SerpicoError::AddressParsingErr(format!(
+            "`{}` should look like <address>:<address>",
+            target
+        ))),
+    }
+}
+
+/// Parses a `<contract>:<storage key>` target, see `parse_storage_key`
+pub fn parse_storage_target(target: &str) -> Result<(Felt, Felt), SerpicoError> {
+    match target.split_once(':') {
+        Some((contract, key)) => Ok((
+            parse_address(contract.to_string())?,
+            parse_storage_key(key)?,
+        )),
+        None => Err(SerpicoError::AddressParsingErr(format!(
+            "`{}` should look like <contract>:<storage key>",
+            target
+        ))),
+    }
+}
+
+/// Parses a storage key, either a felt such as `0x1a2b` or a storage variable such as
+/// `ERC20_total_supply`, `ERC20_balances(0x0123)` for a mapping entry (`sn_keccak` of the name
+/// followed by pedersen hashes of the arguments) or `ERC20_balances(0x0123)+1` for the next slot
+pub fn parse_storage_key(key_str: &str) -> Result<Felt, SerpicoError> {
+    let invalid = |reason: &str| {
+        SerpicoError::AddressParsingErr(format!("storage key `{}` {}", key_str, reason))
     };
+    let key_str = key_str.trim();
+    if key_str.starts_with("0x") {
+        return Felt::from_hex(key_str).map_err(|_| invalid("is not a valid felt"));
+    }
 
-    let block_end = match block_parts[1] {
-        "" | "latest" => last_block,
-        x => x.parse::<u64>().map_err(SerpicoError::IntParsingErr)?,
+    let (variable, offset) = match key_str.rsplit_once('+') {
+        Some((variable, offset)) => (
+            variable.trim(),
+            offset
+                .trim()
+                .parse::<u64>()
+                .map_err(|_| invalid("has an invalid offset"))?,
+        ),
+        None => (key_str, 0),
+    };
+    let (name, args) = match variable.split_once('(') {
+        Some((name, args)) => {
+            let args = args
+                .strip_suffix(')')
+                .ok_or_else(|| invalid("has an unclosed argument list"))?;
+            let args = args
+                .split(',')
+                .map(|arg| parse_felt(arg.trim()).ok_or_else(|| invalid("has an invalid argument")))
+                .collect::<Result<Vec<_>, _>>()?;
+            (name.trim(), args)
+        }
+        None => (variable, Vec::new()),
     };
+    if name.is_empty() {
+        return Err(invalid("has no variable name"));
+    }
+
+    let address = get_storage_var_address(name, &args).map_err(|_| invalid("is not ASCII"))?;
+    Ok(address + Felt::from(offset))
+}
+
+/// Felt written in hex with a `0x` prefix, or in decimal
+fn parse_felt(felt: &str) -> Option<Felt> {
+    if felt.starts_with("0x") {
+        Felt::from_hex(felt).ok()
+    } else {
+        Felt::from_dec_str(felt).ok()
+    }
+}
+
+/// Collects call targets from a comma separated list and from a file holding one target per
+/// line, where empty lines and `#` comments are skipped
+pub fn parse_targets(
+    targets: Option<String>,
+    targets_file: Option<String>,
+) -> Result<Vec<String>, SerpicoError> {
+    let mut parsed = Vec::new();
+    if let Some(targets) = targets {
+        // commas between parentheses separate the arguments of a storage key
+        let (mut depth, mut start) = (0, 0);
+        for (i, c) in targets.char_indices().chain([(targets.len(), ',')]) {
+            match c {
+                '(' => depth += 1,
+                ')' => depth -= 1,
+                ',' if depth == 0 => {
+                    let target = targets[start..i].trim();
+                    if !target.is_empty() {
+                        parsed.push(target.to_string());
+                    }
+                    start = i + 1;
+                }
+                _ => (),
+            }
+        }
+    }
+    if let Some(targets_file) = targets_file {
+        let content = read_to_string(targets_file).map_err(SerpicoError::IoErr)?;
+        parsed.extend(
+            content
+                .lines()
+                .map(|line| line.trim().to_string())
+                .filter(|line| !line.is_empty() && !line.starts_with('#')),
+        );
+    }
+    Ok(parsed)
+}
+
+/// Directory layout of the output files
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Partition {
+    None,
+    Date,
+    BlockBucket(u64),
+}
+
+impl Partition {
+    /// Hive-style directory holding a row, e.g. `date=2024-03-01` or `block_bucket=500000`
+    pub fn key(self, block_number: u64, timestamp: u64) -> String {
+        match self {
+            Partition::None => String::new(),
+            Partition::Date => format!(
+                "date={}",
+                DateTime::from_timestamp(timestamp as i64, 0)
+                    .unwrap_or_default()
+                    .format("%Y-%m-%d")
+            ),
+            Partition::BlockBucket(size) => {
+                format!("block_bucket={}", block_number / size * size)
+            }
+        }
+    }
+
+    /// Whether a directory was created by this partitioning
+    pub fn matches(self, dir_name: &str) -> bool {
+        match self {
+            Partition::None => false,
+            Partition::Date => dir_name.starts_with("date="),
+            Partition::BlockBucket(_) => dir_name.starts_with("block_bucket="),
+        }
+    }
+}
+
+/// Status a block must have reached to be exported
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Finality {
+    // accepted on L2, up to the chain head
+    L2,
+    // accepted on L1, once its state update is proven on Ethereum
+    L1,
+}
+
+/// Parses the finality of exported blocks: `l2` or `l1`
+pub fn parse_finality(finality_str: String) -> Result<Finality, SerpicoError> {
+    match finality_str.as_str() {
+        "l2" => Ok(Finality::L2),
+        "l1" => Ok(Finality::L1),
+        _ => Err(SerpicoError::BlockParsingErr(format!(
+            "`{}` should be l2 or l1",
+            finality_str
+        ))),
+    }
+}
+
+/// Parses the output layout: `none`, `date` or `block:<bucket size>` such as `block:100k`
+pub fn parse_partition(partition_str: String) -> Result<Partition, SerpicoError> {
+    match partition_str.split_once(':') {
+        None if partition_str == "none" => Ok(Partition::None),
+        None if partition_str == "date" => Ok(Partition::Date),
+        Some(("block", size)) => match parse_number(size)? {
+            0 => Err(SerpicoError::PartitionParsingErr(
+                "block bucket size must be positive".to_string(),
+            )),
+            size => Ok(Partition::BlockBucket(size)),
+        },
+        _ => Err(SerpicoError::PartitionParsingErr(format!(
+            "`{}` should be none, date or block:<bucket size>",
+            partition_str
+        ))),
+    }
+}
+
+/// Parses a block selection such as `585084`, `100:200,300:400`, `-1000:latest`,
+/// `:+500`, `1M:2M` or `0:1M:1000` (one block every 1000).
+///
+/// `pending` is accepted as an alias of `latest`: the pending block has no number
+/// yet, so ranges always stop at the chain head.
+pub fn parse_blocks(block_str: String, last_block: u64) -> Result<Vec<BlockRange>, SerpicoError> {
+    let mut ranges = Vec::new();
+    for range_str in block_str.split(',') {
+        ranges.push(parse_block_range(range_str.trim(), last_block)?);
+    }
+
+    check_block_ranges(&mut ranges)?;
+    Ok(ranges)
+}
+
+/// Sorts block ranges and rejects overlapping ones
+pub fn check_block_ranges(ranges: &mut [BlockRange]) -> Result<(), SerpicoError> {
+    ranges.sort_by_key(|range| range.start);
+    for pair in ranges.windows(2) {
+        if pair[1].start <= pair[0].end {
+            return Err(SerpicoError::BlockParsingErr(format!(
+                "block ranges {}:{} and {}:{} overlap",
+                pair[0].start, pair[0].end, pair[1].start, pair[1].end
+            )));
+        }
+    }
+    Ok(())
+}
+
+/// Time intervals as (start, end), `None` bounds mean genesis / chain head
+pub type TimeRanges = Vec<(Option<u64>, Option<u64>)>;
+
+/// Parses unix timestamp intervals such as `1704067200:1706745600`, end excluded.
+///
+/// Empty bounds are returned as `None` and mean genesis / chain head.
+pub fn parse_timestamps(timestamp_str: String) -> Result<TimeRanges, SerpicoError> {
+    parse_time_ranges(&timestamp_str, |timestamp| {
+        timestamp.parse::<u64>().map_err(|_| {
+            SerpicoError::DateParsingErr(format!("`{}` is not a unix timestamp", timestamp))
+        })
+    })
+}
+
+/// Parses UTC date intervals such as `2024-01-01:2024-02-01`, end excluded.
+///
+/// Empty bounds are returned as `None` and mean genesis / chain head.
+pub fn parse_dates(date_str: String) -> Result<TimeRanges, SerpicoError> {
+    parse_time_ranges(&date_str, |date| {
+        let date = NaiveDate::parse_from_str(date, "%Y-%m-%d").map_err(|_| {
+            SerpicoError::DateParsingErr(format!("`{}` is not a YYYY-MM-DD date", date))
+        })?;
+        Ok(date.and_hms_opt(0, 0, 0).unwrap().and_utc().timestamp() as u64)
+    })
+}
+
+fn parse_time_ranges(
+    range_str: &str,
+    parse_bound: impl Fn(&str) -> Result<u64, SerpicoError>,
+) -> Result<TimeRanges, SerpicoError> {
+    let mut ranges = Vec::new();
+    for range in range_str.split(',') {
+        let (start, end) = match range.trim().split_once(':') {
+            Some((start, end)) if !end.contains(':') => (start, end),
+            _ => {
+                return Err(SerpicoError::DateParsingErr(format!(
+                    "`{}` should look like start:end",
+                    range
+                )))
+            }
+        };
+        let start = match start {
+            "" => None,
+            x => Some(parse_bound(x)?),
+        };
+        let end = match end {
+            "" => None,
+            x => Some(parse_bound(x)?),
+        };
+        if let (Some(start), Some(end)) = (start, end) {
+            if start >= end {
+                return Err(SerpicoError::DateParsingErr(format!(
+                    "`{}` ends before it starts",
+                    range
+                )));
+            }
+        }
+        ranges.push((start, end));
+    }
+    Ok(ranges)
+}
+
+fn parse_block_range(range_str: &str, last_block: u64) -> Result<BlockRange, SerpicoError> {
+    let block_parts: Vec<&str> = range_str.split(':').collect();
+
+    let (block_start, block_end, step) = match block_parts[..] {
+        [""] => {
+            return Err(SerpicoError::BlockParsingErr(
+                "empty block range".to_string(),
+            ))
+        }
+        [block] => {
+            let block = parse_block_number(block, last_block)?;
+            (block, block, 1)
+        }
+        [start, end] => {
+            let (block_start, block_end) = parse_bounds(start, end, last_block)?;
+            (block_start, block_end, 1)
+        }
+        [start, end, step] => {
+            let (block_start, block_end) = parse_bounds(start, end, last_block)?;
+            (block_start, block_end, parse_number(step)?)
+        }
+        _ => {
+            return Err(SerpicoError::BlockParsingErr(format!(
+                "`{}` should look like start:end or start:end:step",
+                range_str
+            )))
+        }
+    };
+
+    if step == 0 {
+        return Err(SerpicoError::BlockParsingErr(format!(
+            "step of `{}` must be positive",
+            range_str
+        )));
+    }
+    if block_start > block_end {
+        return Err(SerpicoError::BlockParsingErr(format!(
+            "start block {} is after end block {} in `{}`",
+            block_start, block_end, range_str
+        )));
+    }
+    if block_end > last_block {
+        return Err(SerpicoError::BlockParsingErr(format!(
+            "end block {} is after the chain head {}",
+            block_end, last_block
+        )));
+    }
+
+    Ok(BlockRange {
+        start: block_start,
+        end: block_end,
+        step,
+    })
+}
+
+fn parse_bounds(start: &str, end: &str, last_block: u64) -> Result<(u64, u64), SerpicoError> {
+    match (start.strip_prefix('-'), end.strip_prefix('+')) {
+        (Some(_), Some(_)) => Err(SerpicoError::BlockParsingErr(format!(
+            "`{}:{}` cannot have both bounds relative",
+            start, end
+        ))),
+        (Some(offset), None) => {
+            let block_end = parse_block_end(end, last_block)?;
+            let offset = parse_number(offset)?;
+            let block_start = block_end.checked_sub(offset).ok_or_else(|| {
+                SerpicoError::BlockParsingErr(format!(
+                    "`{}` goes before the genesis block when counted from {}",
+                    start, block_end
+                ))
+            })?;
+            Ok((block_start, block_end))
+        }
+        (None, Some(offset)) => {
+            let block_start = parse_block_start(start, last_block)?;
+            let block_end = block_start
+                .checked_add(parse_number(offset)?)
+                .ok_or_else(|| {
+                    SerpicoError::BlockParsingErr(format!(
+                        "`{}:{}` ends after the last possible block",
+                        start, end
+                    ))
+                })?;
+            Ok((block_start, block_end))
+        }
+        (None, None) => Ok((
+            parse_block_start(start, last_block)?,
+            parse_block_end(end, last_block)?,
+        )),
+    }
+}
+
+fn parse_block_start(block: &str, last_block: u64) -> Result<u64, SerpicoError> {
+    match block {
+        "" => Ok(DEFAULT_START_BLOCK),
+        x => parse_block_number(x, last_block),
+    }
+}
+
+fn parse_block_end(block: &str, last_block: u64) -> Result<u64, SerpicoError> {
+    match block {
+        "" => Ok(last_block),
+        x => parse_block_number(x, last_block),
+    }
+}
+
+fn parse_block_number(block: &str, last_block: u64) -> Result<u64, SerpicoError> {
+    match block {
+        "latest" | "pending" => Ok(last_block),
+        x => parse_number(x),
+    }
+}
+
+/// Parses an integer with an optional `k`, `M` or `B` size suffix, e.g. `500k` or `1.5M`
+fn parse_number(number: &str) -> Result<u64, SerpicoError> {
+    let invalid = || SerpicoError::BlockParsingErr(format!("`{}` is not a valid number", number));
+
+    let (mantissa, multiplier) = match number.chars().last() {
+        Some('k' | 'K') => (&number[..number.len() - 1], 1_000),
+        Some('m' | 'M') => (&number[..number.len() - 1], 1_000_000),
+        Some('b' | 'B') => (&number[..number.len() - 1], 1_000_000_000),
+        _ => (number, 1),
+    };
+
+    if let Ok(value) = mantissa.parse::<u64>() {
+        return value.checked_mul(multiplier).ok_or_else(invalid);
+    }
+
+    let value = mantissa.parse::<f64>().map_err(|_| invalid())? * multiplier as f64;
+    if multiplier == 1 || value < 0.0 || value.fract() != 0.0 {
+        return Err(invalid());
+    }
+    Ok(value as u64)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn range(start: u64, end: u64, step: u64) -> BlockRange {
+        BlockRange { start, end, step }
+    }
+
+    #[test]
+    fn parses_storage_keys() {
+        let balances = get_storage_var_address("ERC20_balances", &[Felt::from(0x123u64)]).unwrap();
+        assert_eq!(
+            parse_storage_key("ERC20_balances(0x123)").unwrap(),
+            balances
+        );
+        assert_eq!(
+            parse_storage_key("ERC20_balances(291) + 1").unwrap(),
+            balances + Felt::ONE
+        );
+        assert_eq!(
+            parse_storage_key("ERC20_allowances(0x1, 0x2)").unwrap(),
+            get_storage_var_address("ERC20_allowances", &[Felt::ONE, Felt::TWO]).unwrap()
+        );
+        assert_eq!(
+            parse_storage_key("ERC20_total_supply").unwrap(),
+            get_storage_var_address("ERC20_total_supply", &[]).unwrap()
+        );
+        assert_eq!(parse_storage_key("0x1a2b").unwrap(), Felt::from(0x1a2bu64));
+        assert!(parse_storage_key("ERC20_balances(0x123").is_err());
+        assert!(parse_storage_key("(0x123)").is_err());
+        assert!(parse_storage_key("ERC20_balances(0x123)+x").is_err());
+        assert!(parse_storage_key("ERC20_balances(zz)").is_err());
+    }
+
+    #[test]
+    fn splits_targets_outside_parentheses() {
+        assert_eq!(
+            parse_targets(
+                Some("0xa:ERC20_allowances(0x1, 0x2), 0xb:0x5,".to_string()),
+                None
+            )
+            .unwrap(),
+            vec!["0xa:ERC20_allowances(0x1, 0x2)", "0xb:0x5"]
+        );
+        assert_eq!(
+            parse_targets(Some("0xa:0xb".to_string()), None).unwrap(),
+            vec!["0xa:0xb"]
+        );
+        assert!(parse_targets(None, None).unwrap().is_empty());
+    }
+
+    #[test]
+    fn names_partitions() {
+        assert_eq!(Partition::Date.key(650_001, 1704067199), "date=2023-12-31");
+        assert_eq!(Partition::Date.key(650_002, 1704067200), "date=2024-01-01");
+        assert_eq!(
+            Partition::BlockBucket(100_000).key(650_001, 0),
+            "block_bucket=600000"
+        );
+        assert_eq!(Partition::BlockBucket(100).key(99, 0), "block_bucket=0");
+        assert!(Partition::Date.matches("date=2024-01-01"));
+        assert!(!Partition::Date.matches("block_bucket=0"));
+        assert!(!Partition::BlockBucket(100).matches("_chunks"));
+    }
+
+    #[test]
+    fn parses_timestamp_ranges() {
+        assert_eq!(
+            parse_timestamps("1704067200:1706745600, 1706745600:".to_string()).unwrap(),
+            vec![
+                (Some(1704067200), Some(1706745600)),
+                (Some(1706745600), None)
+            ]
+        );
+        assert_eq!(
+            parse_timestamps(":1704067200".to_string()).unwrap(),
+            vec![(None, Some(1704067200))]
+        );
+        assert!(parse_timestamps("1706745600:1704067200".to_string()).is_err());
+        assert!(parse_timestamps("1704067200:1704067200".to_string()).is_err());
+        assert!(parse_timestamps("1704067200".to_string()).is_err());
+        assert!(parse_timestamps("1:2:3".to_string()).is_err());
+        assert!(parse_timestamps("2024-01-01:".to_string()).is_err());
+    }
+
+    #[test]
+    fn parses_date_ranges_as_utc_midnights() {
+        assert_eq!(
+            parse_dates("2024-01-01:2024-02-01".to_string()).unwrap(),
+            vec![(Some(1704067200), Some(1706745600))]
+        );
+        assert_eq!(
+            parse_dates(":2024-01-01,2024-02-01:".to_string()).unwrap(),
+            vec![(None, Some(1704067200)), (Some(1706745600), None)]
+        );
+        assert!(parse_dates("2024-02-01:2024-01-01".to_string()).is_err());
+        assert!(parse_dates("2024-13-01:".to_string()).is_err());
+        assert!(parse_dates("1704067200:".to_string()).is_err());
+    }
+
+    #[test]
+    fn parses_numbers_with_suffixes() {
+        assert_eq!(parse_number("1234").unwrap(), 1234);
+        assert_eq!(parse_number("500k").unwrap(), 500_000);
+        assert_eq!(parse_number("1.5M").unwrap(), 1_500_000);
+        assert_eq!(parse_number("2B").unwrap(), 2_000_000_000);
+        assert!(parse_number("1.5").is_err());
+        assert!(parse_number("1.0000005k").is_err());
+        assert!(parse_number("-1k").is_err());
+        assert!(parse_number("abc").is_err());
+        assert!(parse_number("20000000000B").is_err());
+    }
+
+    #[test]
+    fn parses_block_ranges() {
+        assert_eq!(
+            parse_blocks("100:200:10, 5".to_string(), 1000).unwrap(),
+            vec![range(5, 5, 1), range(100, 200, 10)]
+        );
+        assert_eq!(
+            parse_blocks("500:".to_string(), 1000).unwrap(),
+            vec![range(500, 1000, 1)]
+        );
+        assert_eq!(
+            parse_blocks("latest".to_string(), 1000).unwrap(),
+            vec![range(1000, 1000, 1)]
+        );
+    }
+
+    #[test]
+    fn parses_relative_bounds() {
+        assert_eq!(
+            parse_blocks("-100:latest".to_string(), 1000).unwrap(),
+            vec![range(900, 1000, 1)]
+        );
+        assert_eq!(
+            parse_blocks("1k:+1k".to_string(), 5000).unwrap(),
+            vec![range(1000, 2000, 1)]
+        );
+        assert!(parse_blocks("-2k:1k".to_string(), 5000).is_err());
+        assert!(parse_blocks("-1:+1".to_string(), 5000).is_err());
+        assert!(parse_blocks("1k:+18446744073709551615".to_string(), 5000).is_err());
+    }
+
+    #[test]
+    fn rejects_invalid_block_ranges() {
+        assert!(parse_blocks("".to_string(), 1000).is_err());
+        assert!(parse_blocks("200:100".to_string(), 1000).is_err());
+        assert!(parse_blocks("0:100:0".to_string(), 1000).is_err());
+        assert!(parse_blocks("0:2000".to_string(), 1000).is_err());
+        assert!(parse_blocks("0:100,50:150".to_string(), 1000).is_err());
+        assert!(parse_blocks("1:2:3:4".to_string(), 1000).is_err());
+    }
 
-    //TODO block requirements
-    Ok((block_start, block_end))
+    #[test]
+    fn finds_first_sampled_block() {
+        let block_range = range(100, 200, 10);
+        assert_eq!(block_range.first_block_from(0), 100);
+        assert_eq!(block_range.first_block_from(120), 120);
+        assert_eq!(block_range.first_block_from(121), 130);
+        assert_eq!(block_range.offset(), 0);
+        assert_eq!(range(103, 200, 10).offset(), 3);
+    }
 }
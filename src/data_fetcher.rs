@@ -1,96 +1,1483 @@
-use crate::{Data, Datasets, SerpicoError};
+use crate::abi::{parse_execute_calldata, u256_to_decimal, AbiDecoder, AbiValue};
+use crate::rpc::{self, backoff, is_trace_unavailable};
+use crate::transfers::{
+    data_layout_tokens, to_erc1155_transfers, to_erc20_transfers, to_erc721_transfers,
+    transfer_batch_selector, transfer_selector, transfer_single_selector,
+};
+use crate::{
+    deployed_address, tx_type_column, AccountNonce, Balance, BlockCall, BlockEvent, BlockStats,
+    BlockTx, ClassBody, Data, Datasets, DeclaredClass, Deployment, Message, SerpicoError,
+    StorageValue,
+};
 use kdam::tqdm;
 use starknet::core::types::{
-    BlockId, EventFilter, MaybePendingBlockWithTxHashes, MaybePendingBlockWithTxs,
+    BlockId, BlockStatus, BlockTag, BlockWithReceipts, BlockWithTxHashes, BlockWithTxs,
+    ContractClass, DeclareTransaction, DeployAccountTransaction, EmittedEvent, EntryPointType,
+    Event, EventFilter, ExecuteInvocation, ExecutionResult, Felt, FunctionCall, FunctionInvocation,
+    InvokeTransaction, MaybePendingBlockWithReceipts, MaybePendingBlockWithTxHashes,
+    MaybePendingBlockWithTxs, MaybePendingStateUpdate, MsgToL1, PriceUnit, ReceiptBlock,
+    ResourcePrice, StateDiff, Transaction, TransactionReceipt, TransactionTrace,
+    TransactionTraceWithHash,
 };
+use starknet::core::utils::starknet_keccak;
 use starknet::providers::jsonrpc::HttpTransport;
-use starknet::providers::{JsonRpcClient, Provider};
+use starknet::providers::{JsonRpcClient, ProviderError};
+use std::collections::{HashMap, HashSet};
+use std::fs::{read_to_string, write};
+use std::iter::from_fn;
+use std::path::Path;
+use std::sync::Mutex;
+
+/// Number of events requested per `get_events` page
+const EVENTS_CHUNK_SIZE: u64 = 1000;
+
+/// Parameters of the datasets, shared by every chunk
+pub struct FetchParams {
+    /// Only fetch events emitted by, and calls made to, this contract
+    pub contract: Option<Felt>,
+    /// Decoder of the `decoded_logs` events and of the `calls` function names
+    pub abi_decoder: Option<AbiDecoder>,
+    /// (token, holder) pairs of the `balances` dataset
+    pub balance_targets: Vec<(Felt, Felt)>,
+    /// (contract, storage key) pairs of the `storage` dataset
+    pub storage_targets: Vec<(Felt, Felt)>,
+    /// Accounts of the `nonces` dataset
+    pub nonce_targets: Vec<Felt>,
+    /// Directory of the class definitions fetched by the `classes` dataset, if requested
+    pub class_bodies_dir: Option<String>,
+    /// Whether Cairo 0 tokens are ERC-721 ones, cached across chunks
+    pub nft_tokens: Mutex<HashMap<Felt, bool>>,
+    /// Reads the L2 gas price of the `blocks` dataset, only set when the node serves the RPC
+    /// spec 0.8 or later
+    pub l2_gas_transport: Option<HttpTransport>,
+}
 
+/// Fetches a chunk of every requested dataset, returned by output table.
+///
+/// Blocks, transactions and calls come from the same `get_block_with_txs` calls when several of
+/// them are requested, and so do the event datasets with `get_events`. The node only returns
+/// the events of `--contract` and, unless raw logs are requested, the selectors of the event
+/// datasets; their positions are read from the receipts of their blocks.
 pub async fn fetch_data(
-    client: JsonRpcClient<HttpTransport>,
-    dataset: Datasets,
+    client: &JsonRpcClient<HttpTransport>,
+    datasets: &[Datasets],
     (block_start, block_end): (u64, u64),
+    step: u64,
     chunk_id: u16,
-) -> Result<Data, SerpicoError> {
-    match dataset {
-        Datasets::Blocks => fetch_blocks(client, (block_start, block_end), chunk_id).await,
-        Datasets::Transactions => fetch_txs(client, (block_start, block_end), chunk_id).await,
-        Datasets::Logs => fetch_logs(client, (block_start, block_end), chunk_id).await,
-        Datasets::None => Ok(Data::None),
+    params: &FetchParams,
+) -> Result<Vec<(String, Data)>, SerpicoError> {
+    let mut data = Vec::new();
+
+    let with_blocks = datasets.contains(&Datasets::Blocks);
+    let with_txs = datasets.contains(&Datasets::Transactions);
+    let with_calls = datasets.contains(&Datasets::Calls);
+    if with_txs || with_calls {
+        let txs = if with_blocks {
+            let blocks =
+                fetch_blocks_with_txs(client, (block_start, block_end), step, chunk_id).await?;
+            let headers = blocks.iter().map(to_block_with_tx_hashes).collect();
+            data.push((
+                Datasets::Blocks.to_name().to_string(),
+                Data::Blocks(with_l2_gas_prices(headers, params).await?),
+            ));
+            to_transactions(blocks)
+        } else {
+            fetch_txs(client, (block_start, block_end), step, chunk_id).await?
+        };
+        if with_calls {
+            data.push((
+                Datasets::Calls.tables(params).remove(0),
+                Data::Calls(to_calls(&txs, params)),
+            ));
+        }
+        if with_txs {
+            data.push((
+                Datasets::Transactions.to_name().to_string(),
+                Data::Transactions(txs),
+            ));
+        }
+    } else if with_blocks {
+        data.push((
+            Datasets::Blocks.to_name().to_string(),
+            fetch_blocks(client, (block_start, block_end), step, chunk_id, params).await?,
+        ));
+    }
+
+    let with_logs = datasets.contains(&Datasets::Logs);
+    let with_decoded_logs = datasets.contains(&Datasets::DecodedLogs);
+    let with_erc20_transfers = datasets.contains(&Datasets::Erc20Transfers);
+    let with_erc721_transfers = datasets.contains(&Datasets::Erc721Transfers);
+    let with_erc1155_transfers = datasets.contains(&Datasets::Erc1155Transfers);
+    if with_logs
+        || with_decoded_logs
+        || with_erc20_transfers
+        || with_erc721_transfers
+        || with_erc1155_transfers
+    {
+        let selectors = if with_logs {
+            None
+        } else {
+            let mut selectors = Vec::new();
+            if with_erc20_transfers || with_erc721_transfers {
+                selectors.push(transfer_selector());
+            }
+            if with_erc1155_transfers {
+                selectors.extend([transfer_single_selector(), transfer_batch_selector()]);
+            }
+            if let (true, Some(abi_decoder)) = (with_decoded_logs, params.abi_decoder.as_ref()) {
+                selectors.extend(abi_decoder.event_selectors());
+            }
+            selectors.sort();
+            selectors.dedup();
+            Some(selectors)
+        };
+        let logs = match selectors {
+            // an empty selector list would match every event
+            Some(selectors) if selectors.is_empty() => Vec::new(),
+            selectors => {
+                fetch_logs(
+                    client,
+                    (block_start, block_end),
+                    step,
+                    params.contract,
+                    selectors,
+                )
+                .await?
+            }
+        };
+        let nft_tokens = if with_erc20_transfers || with_erc721_transfers {
+            fetch_nft_tokens(client, data_layout_tokens(&logs), block_end, params).await?
+        } else {
+            HashSet::new()
+        };
+        if with_erc20_transfers {
+            data.push((
+                Datasets::Erc20Transfers.tables(params).remove(0),
+                Data::Erc20Transfers(to_erc20_transfers(&logs, &nft_tokens)),
+            ));
+        }
+        if with_erc721_transfers {
+            data.push((
+                Datasets::Erc721Transfers.tables(params).remove(0),
+                Data::Erc721Transfers(to_erc721_transfers(&logs, &nft_tokens)),
+            ));
+        }
+        if with_erc1155_transfers {
+            data.push((
+                Datasets::Erc1155Transfers.tables(params).remove(0),
+                Data::Erc1155Transfers(to_erc1155_transfers(&logs)),
+            ));
+        }
+        if let (true, Some(abi_decoder)) = (with_decoded_logs, params.abi_decoder.as_ref()) {
+            data.extend(
+                Datasets::DecodedLogs
+                    .tables(params)
+                    .into_iter()
+                    .zip(decode_logs(abi_decoder, &logs)),
+            );
+        }
+        if with_logs {
+            data.push((Datasets::Logs.tables(params).remove(0), Data::Logs(logs)));
+        }
     }
+
+    if datasets.contains(&Datasets::Balances) {
+        let balances = fetch_balances(
+            client,
+            (block_start, block_end),
+            step,
+            &params.balance_targets,
+            chunk_id,
+        )
+        .await?;
+        data.push((
+            Datasets::Balances.tables(params).remove(0),
+            Data::Balances(balances),
+        ));
+    }
+
+    if datasets.contains(&Datasets::Storage) {
+        let values = fetch_storage(
+            client,
+            (block_start, block_end),
+            step,
+            &params.storage_targets,
+            chunk_id,
+        )
+        .await?;
+        data.push((
+            Datasets::Storage.tables(params).remove(0),
+            Data::Storage(values),
+        ));
+    }
+
+    if datasets.contains(&Datasets::Nonces) {
+        let nonces = fetch_nonces(
+            client,
+            (block_start, block_end),
+            step,
+            &params.nonce_targets,
+            chunk_id,
+        )
+        .await?;
+        data.push((
+            Datasets::Nonces.tables(params).remove(0),
+            Data::Nonces(nonces),
+        ));
+    }
+
+    if datasets.contains(&Datasets::Contracts) {
+        data.push((
+            Datasets::Contracts.to_name().to_string(),
+            Data::Contracts(
+                fetch_contracts(client, (block_start, block_end), step, chunk_id).await?,
+            ),
+        ));
+    }
+
+    if datasets.contains(&Datasets::Classes) {
+        let classes = fetch_classes(
+            client,
+            (block_start, block_end),
+            step,
+            params.class_bodies_dir.as_deref(),
+            chunk_id,
+        )
+        .await?;
+        data.push((
+            Datasets::Classes.tables(params).remove(0),
+            Data::Classes {
+                classes,
+                with_bodies: params.class_bodies_dir.is_some(),
+            },
+        ));
+    }
+
+    if datasets.contains(&Datasets::Messages) {
+        data.push((
+            Datasets::Messages.to_name().to_string(),
+            Data::Messages(fetch_messages(client, (block_start, block_end), step, chunk_id).await?),
+        ));
+    }
+
+    if datasets.contains(&Datasets::BlockStats) {
+        data.push((
+            Datasets::BlockStats.to_name().to_string(),
+            Data::BlockStats(
+                fetch_block_stats(client, (block_start, block_end), step, chunk_id).await?,
+            ),
+        ));
+    }
+
+    Ok(data)
 }
 
 pub async fn fetch_blocks(
-    client: JsonRpcClient<HttpTransport>,
+    client: &JsonRpcClient<HttpTransport>,
     (block_start, block_end): (u64, u64),
+    step: u64,
     chunk_id: u16,
+    params: &FetchParams,
 ) -> Result<Data, SerpicoError> {
     let mut data = Vec::new();
     for block in tqdm!(
-        block_start..(block_end + 1),
+        (block_start..(block_end + 1)).step_by(step as usize),
         desc = format!("block {} to {}", block_start, block_end),
         position = chunk_id
     ) {
-        let mut continuer = true;
-        while continuer {
-            continuer = false;
-            match client
-                .get_block_with_tx_hashes(BlockId::Number(block))
+        data.push(fetch_block_with_tx_hashes(client, block).await?);
+    }
+
+    Ok(Data::Blocks(with_l2_gas_prices(data, params).await?))
+}
+
+/// Pairs blocks with their L2 gas price, left empty when the node serves an RPC spec older than
+/// 0.8
+async fn with_l2_gas_prices(
+    blocks: Vec<BlockWithTxHashes>,
+    params: &FetchParams,
+) -> Result<Vec<(BlockWithTxHashes, Option<ResourcePrice>)>, SerpicoError> {
+    let Some(transport) = &params.l2_gas_transport else {
+        return Ok(blocks.into_iter().map(|block| (block, None)).collect());
+    };
+    let mut data = Vec::new();
+    for block in blocks {
+        let l2_gas_price =
+            rpc::get_block_l2_gas_price(transport, BlockId::Number(block.block_number))
                 .await
+                .map_err(SerpicoError::ClientErr)?;
+        data.push((block, l2_gas_price));
+    }
+
+    Ok(data)
+}
+
+pub async fn fetch_txs(
+    client: &JsonRpcClient<HttpTransport>,
+    (block_start, block_end): (u64, u64),
+    step: u64,
+    chunk_id: u16,
+) -> Result<Vec<BlockTx>, SerpicoError> {
+    let blocks = fetch_blocks_with_txs(client, (block_start, block_end), step, chunk_id).await?;
+    Ok(to_transactions(blocks))
+}
+
+async fn fetch_blocks_with_txs(
+    client: &JsonRpcClient<HttpTransport>,
+    (block_start, block_end): (u64, u64),
+    step: u64,
+    chunk_id: u16,
+) -> Result<Vec<BlockWithTxs>, SerpicoError> {
+    let mut data = Vec::new();
+    for block in tqdm!(
+        (block_start..(block_end + 1)).step_by(step as usize),
+        desc = format!("block {} to {}", block_start, block_end),
+        position = chunk_id
+    ) {
+        data.push(fetch_block_with_txs(client, block).await?);
+    }
+
+    Ok(data)
+}
+
+fn to_block_with_tx_hashes(block: &BlockWithTxs) -> BlockWithTxHashes {
+    BlockWithTxHashes {
+        status: block.status,
+        block_hash: block.block_hash,
+        parent_hash: block.parent_hash,
+        block_number: block.block_number,
+        new_root: block.new_root,
+        timestamp: block.timestamp,
+        sequencer_address: block.sequencer_address,
+        l1_gas_price: block.l1_gas_price.clone(),
+        l1_data_gas_price: block.l1_data_gas_price.clone(),
+        l1_da_mode: block.l1_da_mode,
+        starknet_version: block.starknet_version.clone(),
+        transactions: block
+            .transactions
+            .iter()
+            .map(|tx| *tx.transaction_hash())
+            .collect(),
+    }
+}
+
+fn to_transactions(blocks: Vec<BlockWithTxs>) -> Vec<BlockTx> {
+    let mut data = Vec::new();
+    for block in blocks {
+        data.extend(
+            block
+                .transactions
+                .into_iter()
+                .enumerate()
+                .map(|(transaction_index, tx)| BlockTx {
+                    tx,
+                    block_number: block.block_number,
+                    block_hash: block.block_hash,
+                    timestamp: block.timestamp,
+                    transaction_index: transaction_index as u64,
+                }),
+        );
+    }
+    data
+}
+
+/// Splits invoke V1 and V3 transactions into the calls of their account `__execute__`
+fn to_calls(txs: &[BlockTx], params: &FetchParams) -> Vec<BlockCall> {
+    let mut data = Vec::new();
+    for tx in txs {
+        let (caller, calldata) = match &tx.tx {
+            Transaction::Invoke(InvokeTransaction::V1(sub_tx)) => {
+                (sub_tx.sender_address, &sub_tx.calldata)
+            }
+            Transaction::Invoke(InvokeTransaction::V3(sub_tx)) => {
+                (sub_tx.sender_address, &sub_tx.calldata)
+            }
+            _ => continue,
+        };
+        let (layout, calls) = match parse_execute_calldata(calldata) {
+            Some(calls) => calls,
+            None => continue,
+        };
+
+        for (call_index, (contract_address, selector, calldata)) in calls.into_iter().enumerate() {
+            if params
+                .contract
+                .is_some_and(|contract| contract != contract_address)
+            {
+                continue;
+            }
+            let (function_name, arguments) = match params
+                .abi_decoder
+                .as_ref()
+                .and_then(|abi_decoder| abi_decoder.decode_call(selector, &calldata))
             {
-                Ok(MaybePendingBlockWithTxHashes::Block(b)) => data.push(b),
-                Ok(MaybePendingBlockWithTxHashes::PendingBlock(_)) => (),
-                Err(_) => continuer = true,
+                Some((name, arguments)) => (Some(name), arguments),
+                None => (None, None),
             };
+            data.push(BlockCall {
+                block_number: tx.block_number,
+                block_hash: tx.block_hash,
+                timestamp: tx.timestamp,
+                transaction_index: tx.transaction_index,
+                transaction_hash: *tx.tx.transaction_hash(),
+                caller,
+                call_index: call_index as u64,
+                layout,
+                contract_address,
+                selector,
+                function_name,
+                calldata,
+                arguments,
+            });
         }
     }
+    data
+}
+
+/// Events of the chunk emitted by `address` with one of the `selectors` as first key, when
+/// given
+pub async fn fetch_logs(
+    client: &JsonRpcClient<HttpTransport>,
+    (block_start, block_end): (u64, u64),
+    step: u64,
+    address: Option<Felt>,
+    selectors: Option<Vec<Felt>>,
+) -> Result<Vec<BlockEvent>, SerpicoError> {
+    // sampled ranges query their blocks one by one rather than every event in between
+    let queried_ranges: Vec<(u64, u64)> = if step == 1 {
+        vec![(block_start, block_end)]
+    } else {
+        (block_start..=block_end)
+            .step_by(step as usize)
+            .map(|block| (block, block))
+            .collect()
+    };
+
+    let mut events = Vec::new();
+    for (from_block, to_block) in queried_ranges {
+        let filter = EventFilter {
+            from_block: Some(BlockId::Number(from_block)),
+            to_block: Some(BlockId::Number(to_block)),
+            address,
+            keys: selectors.clone().map(|selectors| vec![selectors]),
+        };
+        events.extend(fetch_events(client, filter).await?);
+    }
 
-    Ok(Data::Blocks(data))
+    with_positions(client, events).await
 }
 
-pub async fn fetch_txs(
-    client: JsonRpcClient<HttpTransport>,
+/// Every event matching the filter, read page by page
+async fn fetch_events(
+    client: &JsonRpcClient<HttpTransport>,
+    filter: EventFilter,
+) -> Result<Vec<EmittedEvent>, SerpicoError> {
+    let mut events = Vec::new();
+    let mut continuation_token = None;
+    loop {
+        let page = rpc::get_events(
+            client,
+            &filter,
+            continuation_token.as_ref(),
+            EVENTS_CHUNK_SIZE,
+        )
+        .await
+        .map_err(SerpicoError::ClientErr)?;
+        events.extend(page.events);
+        continuation_token = page.continuation_token;
+        if continuation_token.is_none() {
+            break;
+        }
+    }
+    Ok(events)
+}
+
+/// Decodes events with the ABI, one table per event type in ABI order
+fn decode_logs(abi_decoder: &AbiDecoder, logs: &[BlockEvent]) -> Vec<Data> {
+    let mut tables: Vec<Vec<(BlockEvent, Vec<AbiValue>)>> =
+        abi_decoder.events().iter().map(|_| Vec::new()).collect();
+    for log in logs {
+        if let Some((index, values)) = abi_decoder.decode_event(&log.event.keys, &log.event.data) {
+            tables[index].push((log.clone(), values));
+        }
+    }
+
+    abi_decoder
+        .events()
+        .iter()
+        .zip(tables)
+        .map(|(event, logs)| Data::DecodedLogs {
+            columns: event.columns.clone(),
+            logs,
+        })
+        .collect()
+}
+
+/// Attaches block timestamps and positions to filtered events, which come ordered by block.
+/// Only the blocks holding some of them are fetched, with their receipts.
+async fn with_positions(
+    client: &JsonRpcClient<HttpTransport>,
+    events: Vec<EmittedEvent>,
+) -> Result<Vec<BlockEvent>, SerpicoError> {
+    let mut data = Vec::with_capacity(events.len());
+    let mut events = events.into_iter().peekable();
+    while let Some(block_number) = events.peek().map(|event| event.block_number.unwrap_or(0)) {
+        let block_events: Vec<EmittedEvent> =
+            from_fn(|| events.next_if(|event| event.block_number.unwrap_or(0) == block_number))
+                .collect();
+        let block = fetch_block_with_receipts(client, block_number).await?;
+        data.extend(position_filtered_events(block_events, &block)?);
+    }
+    Ok(data)
+}
+
+/// Finds filtered events among the receipt events of their block, so that their indexes count
+/// the events left out by the filter. Identical events of a transaction are matched in order.
+fn position_filtered_events(
+    events: Vec<EmittedEvent>,
+    block: &BlockWithReceipts,
+) -> Result<Vec<BlockEvent>, SerpicoError> {
+    // transaction index, index of its first event in the block and its events, per transaction
+    let mut transactions: HashMap<Felt, (u64, u64, &[Event])> = HashMap::new();
+    let mut first_event_index = 0;
+    for (transaction_index, tx) in block.transactions.iter().enumerate() {
+        let receipt_events = receipt_events(&tx.receipt);
+        transactions.insert(
+            *tx.receipt.transaction_hash(),
+            (transaction_index as u64, first_event_index, receipt_events),
+        );
+        first_event_index += receipt_events.len() as u64;
+    }
+
+    // receipt events already matched, per transaction
+    let mut matched: HashMap<Felt, usize> = HashMap::new();
+    let mut data = Vec::new();
+    for event in events {
+        let missing = || {
+            SerpicoError::BlockParsingErr(format!(
+                "event of transaction 0x{:x} is missing from the receipts of block {}",
+                event.transaction_hash, block.block_number
+            ))
+        };
+        let (transaction_index, first_event_index, receipt_events) = *transactions
+            .get(&event.transaction_hash)
+            .ok_or_else(missing)?;
+        let start = matched.entry(event.transaction_hash).or_insert(0);
+        let transaction_event_index = receipt_events[*start..]
+            .iter()
+            .position(|receipt_event| {
+                receipt_event.from_address == event.from_address
+                    && receipt_event.keys == event.keys
+                    && receipt_event.data == event.data
+            })
+            .map(|offset| *start + offset)
+            .ok_or_else(missing)?;
+        *start = transaction_event_index + 1;
+        data.push(BlockEvent {
+            timestamp: block.timestamp,
+            transaction_index: Some(transaction_index),
+            event_index: first_event_index + transaction_event_index as u64,
+            transaction_event_index: transaction_event_index as u64,
+            event,
+        });
+    }
+    Ok(data)
+}
+
+/// Numbers every event of a block within the block and its transactions, from the timestamp and
+/// transaction indexes of each block
+fn position_events(
+    events: Vec<EmittedEvent>,
+    blocks: &HashMap<u64, (u64, HashMap<Felt, u64>)>,
+) -> Vec<BlockEvent> {
+    let mut data = Vec::new();
+    let mut previous_event: Option<(u64, Felt)> = None;
+    let (mut event_index, mut transaction_event_index) = (0, 0);
+    for event in events {
+        let block = event.block_number.unwrap_or(0);
+        match previous_event {
+            Some((previous_block, _)) if previous_block != block => {
+                event_index = 0;
+                transaction_event_index = 0;
+            }
+            Some((_, previous_tx)) if previous_tx != event.transaction_hash => {
+                event_index += 1;
+                transaction_event_index = 0;
+            }
+            Some(_) => {
+                event_index += 1;
+                transaction_event_index += 1;
+            }
+            None => (),
+        }
+        previous_event = Some((block, event.transaction_hash));
+
+        let (timestamp, transaction_indexes) = &blocks[&block];
+        data.push(BlockEvent {
+            timestamp: *timestamp,
+            transaction_index: transaction_indexes.get(&event.transaction_hash).copied(),
+            event_index,
+            transaction_event_index,
+            event,
+        });
+    }
+    data
+}
+
+/// Balance of every (token, holder) pair at each block of the chunk
+pub async fn fetch_balances(
+    client: &JsonRpcClient<HttpTransport>,
     (block_start, block_end): (u64, u64),
+    step: u64,
+    targets: &[(Felt, Felt)],
     chunk_id: u16,
-) -> Result<Data, SerpicoError> {
+) -> Result<Vec<Balance>, SerpicoError> {
+    // entry point answering balance calls, per token
+    let mut entry_points: HashMap<Felt, &str> = HashMap::new();
     let mut data = Vec::new();
     for block in tqdm!(
-        block_start..(block_end + 1),
+        (block_start..(block_end + 1)).step_by(step as usize),
         desc = format!("block {} to {}", block_start, block_end),
         position = chunk_id
     ) {
-        let mut continuer = true;
-        while continuer {
-            continuer = false;
-            match client.get_block_with_txs(BlockId::Number(block)).await {
-                Ok(MaybePendingBlockWithTxs::Block(b)) => {
-                    data.extend(b.transactions.iter().map(|t| (t.clone(), block)))
+        let timestamp = fetch_block_with_tx_hashes(client, block).await?.timestamp;
+        for (token, holder) in targets {
+            let balance = match entry_points.get(token) {
+                Some(entry_point) => {
+                    call_balance(client, *token, *holder, block, entry_point).await?
+                }
+                None => {
+                    let mut balance = None;
+                    // Cairo 0 tokens use camel case, Cairo 1 tokens snake case
+                    for entry_point in ["balanceOf", "balance_of"] {
+                        balance = call_balance(client, *token, *holder, block, entry_point).await?;
+                        if balance.is_some() {
+                            entry_points.insert(*token, entry_point);
+                            break;
+                        }
+                    }
+                    balance
                 }
-                Ok(MaybePendingBlockWithTxs::PendingBlock(_)) => (),
-                Err(_) => continuer = true,
             };
+            data.push(Balance {
+                block_number: block,
+                timestamp,
+                token: *token,
+                holder: *holder,
+                balance,
+            });
         }
     }
 
-    Ok(Data::Transactions(data))
+    Ok(data)
 }
 
-pub async fn fetch_logs(
-    client: JsonRpcClient<HttpTransport>,
+/// `u256` balance returned by `entry_point`, `None` when the call fails on chain
+/// (e.g. the token is not deployed yet)
+async fn call_balance(
+    client: &JsonRpcClient<HttpTransport>,
+    token: Felt,
+    holder: Felt,
+    block: u64,
+    entry_point: &str,
+) -> Result<Option<String>, SerpicoError> {
+    let request = FunctionCall {
+        contract_address: token,
+        entry_point_selector: starknet_keccak(entry_point.as_bytes()),
+        calldata: vec![holder],
+    };
+    match rpc::call(client, &request, BlockId::Number(block)).await {
+        Ok(result) => Ok(match result[..] {
+            [low, high] => Some(u256_to_decimal(low, high)),
+            [value] => Some(value.to_string()),
+            _ => None,
+        }),
+        Err(ProviderError::StarknetError(_)) => Ok(None),
+        Err(err) => Err(SerpicoError::ClientErr(err)),
+    }
+}
+
+/// ERC-721 tokens among Cairo 0 ones, which emit `Transfer` events with the ERC-20 layout but
+/// have no `decimals` entry point
+async fn fetch_nft_tokens(
+    client: &JsonRpcClient<HttpTransport>,
+    tokens: HashSet<Felt>,
+    block: u64,
+    params: &FetchParams,
+) -> Result<HashSet<Felt>, SerpicoError> {
+    let mut nft_tokens = HashSet::new();
+    for token in tokens {
+        let cached = params.nft_tokens.lock().unwrap().get(&token).copied();
+        let is_nft = match cached {
+            Some(is_nft) => is_nft,
+            None => {
+                let request = FunctionCall {
+                    contract_address: token,
+                    entry_point_selector: starknet_keccak(b"decimals"),
+                    calldata: vec![],
+                };
+                let is_nft = match rpc::call(client, &request, BlockId::Number(block)).await {
+                    Ok(_) => false,
+                    // e.g. entry point not found
+                    Err(ProviderError::StarknetError(_)) => true,
+                    Err(err) => return Err(SerpicoError::ClientErr(err)),
+                };
+                params.nft_tokens.lock().unwrap().insert(token, is_nft);
+                is_nft
+            }
+        };
+        if is_nft {
+            nft_tokens.insert(token);
+        }
+    }
+    Ok(nft_tokens)
+}
+
+/// Value of every (contract, storage key) pair at each block of the chunk
+pub async fn fetch_storage(
+    client: &JsonRpcClient<HttpTransport>,
     (block_start, block_end): (u64, u64),
+    step: u64,
+    targets: &[(Felt, Felt)],
     chunk_id: u16,
-) -> Result<Data, SerpicoError> {
-    let filter = EventFilter {
-        from_block: Some(BlockId::Number(block_start)),
-        to_block: Some(BlockId::Number(block_end)),
-        address: None,
-        keys: None,
+) -> Result<Vec<StorageValue>, SerpicoError> {
+    let mut data = Vec::new();
+    for block in tqdm!(
+        (block_start..(block_end + 1)).step_by(step as usize),
+        desc = format!("block {} to {}", block_start, block_end),
+        position = chunk_id
+    ) {
+        let timestamp = fetch_block_with_tx_hashes(client, block).await?.timestamp;
+        for (contract, key) in targets {
+            let value =
+                match rpc::get_storage_at(client, *contract, *key, BlockId::Number(block)).await {
+                    Ok(value) => Some(value),
+                    // the contract is not deployed yet
+                    Err(ProviderError::StarknetError(_)) => None,
+                    Err(err) => return Err(SerpicoError::ClientErr(err)),
+                };
+            data.push(StorageValue {
+                block_number: block,
+                timestamp,
+                contract: *contract,
+                key: *key,
+                value,
+            });
+        }
+    }
+
+    Ok(data)
+}
+
+/// Nonce of every account at each block of the chunk
+pub async fn fetch_nonces(
+    client: &JsonRpcClient<HttpTransport>,
+    (block_start, block_end): (u64, u64),
+    step: u64,
+    accounts: &[Felt],
+    chunk_id: u16,
+) -> Result<Vec<AccountNonce>, SerpicoError> {
+    let mut data = Vec::new();
+    for block in tqdm!(
+        (block_start..(block_end + 1)).step_by(step as usize),
+        desc = format!("block {} to {}", block_start, block_end),
+        position = chunk_id
+    ) {
+        let timestamp = fetch_block_with_tx_hashes(client, block).await?.timestamp;
+        for account in accounts {
+            let nonce = match rpc::get_nonce(client, BlockId::Number(block), *account).await {
+                Ok(nonce) => Some(nonce),
+                // the account is not deployed yet
+                Err(ProviderError::StarknetError(_)) => None,
+                Err(err) => return Err(SerpicoError::ClientErr(err)),
+            };
+            data.push(AccountNonce {
+                block_number: block,
+                timestamp,
+                account: *account,
+                nonce,
+            });
+        }
+    }
+
+    Ok(data)
+}
+
+/// Contracts deployed in the chunk.
+///
+/// The state diff lists every deployed contract, transactions and traces tell how and by whom:
+/// legacy `Deploy` transactions, `DeployAccount` constructors and `deploy_syscall` constructors
+/// nested in invoke or L1 handler executions.
+pub async fn fetch_contracts(
+    client: &JsonRpcClient<HttpTransport>,
+    (block_start, block_end): (u64, u64),
+    step: u64,
+    chunk_id: u16,
+) -> Result<Vec<Deployment>, SerpicoError> {
+    let mut data = Vec::new();
+    for block in tqdm!(
+        (block_start..(block_end + 1)).step_by(step as usize),
+        desc = format!("block {} to {}", block_start, block_end),
+        position = chunk_id
+    ) {
+        let state_diff = fetch_state_diff(client, block).await?;
+        if state_diff.deployed_contracts.is_empty() {
+            continue;
+        }
+        let mut class_hashes: HashMap<Felt, Felt> = state_diff
+            .deployed_contracts
+            .iter()
+            .map(|item| (item.address, item.class_hash))
+            .collect();
+
+        let block_with_txs = fetch_block_with_txs(client, block).await?;
+        // (transaction, deployer, contract, class hash, deployment type)
+        let mut deployments = Vec::new();
+        for tx in block_with_txs.transactions.iter() {
+            // accounts are deployed at an address derived from the transaction, traces or not
+            let (salt, class_hash, constructor_calldata, deployment_type) = match tx {
+                Transaction::Deploy(sub_tx) => (
+                    sub_tx.contract_address_salt,
+                    sub_tx.class_hash,
+                    &sub_tx.constructor_calldata,
+                    "deploy",
+                ),
+                Transaction::DeployAccount(DeployAccountTransaction::V1(sub_tx)) => (
+                    sub_tx.contract_address_salt,
+                    sub_tx.class_hash,
+                    &sub_tx.constructor_calldata,
+                    "deploy_account",
+                ),
+                Transaction::DeployAccount(DeployAccountTransaction::V3(sub_tx)) => (
+                    sub_tx.contract_address_salt,
+                    sub_tx.class_hash,
+                    &sub_tx.constructor_calldata,
+                    "deploy_account",
+                ),
+                _ => continue,
+            };
+            deployments.push((
+                *tx.transaction_hash(),
+                None,
+                deployed_address(salt, class_hash, constructor_calldata),
+                class_hash,
+                deployment_type,
+            ));
+        }
+        for trace in fetch_block_traces(client, block).await? {
+            let mut constructors = Vec::new();
+            match trace.trace_root {
+                TransactionTrace::DeployAccount(deploy_trace) => {
+                    collect_constructors(
+                        &deploy_trace.constructor_invocation.calls,
+                        &mut constructors,
+                    );
+                }
+                TransactionTrace::Invoke(invoke_trace) => {
+                    if let ExecuteInvocation::Success(invocation) = invoke_trace.execute_invocation
+                    {
+                        collect_constructors(&[invocation], &mut constructors);
+                    }
+                }
+                TransactionTrace::L1Handler(l1_handler_trace) => {
+                    collect_constructors(
+                        &[l1_handler_trace.function_invocation],
+                        &mut constructors,
+                    );
+                }
+                TransactionTrace::Declare(_) => (),
+            }
+            deployments.extend(constructors.into_iter().map(|invocation| {
+                (
+                    trace.transaction_hash,
+                    Some(invocation.caller_address),
+                    invocation.contract_address,
+                    invocation.class_hash,
+                    "deploy_syscall",
+                )
+            }));
+        }
+
+        for (transaction_hash, deployer, contract_address, class_hash, deployment_type) in
+            deployments
+        {
+            // deployments missing from the state diff were reverted
+            if let Some(state_class_hash) = class_hashes.remove(&contract_address) {
+                data.push(Deployment {
+                    block_number: block,
+                    block_hash: block_with_txs.block_hash,
+                    timestamp: block_with_txs.timestamp,
+                    transaction_hash: Some(transaction_hash),
+                    deployer,
+                    contract_address,
+                    class_hash: if state_class_hash == Felt::ZERO {
+                        class_hash
+                    } else {
+                        state_class_hash
+                    },
+                    deployment_type: Some(deployment_type),
+                });
+            }
+        }
+        // without traces, only the state diff is known
+        for item in state_diff.deployed_contracts {
+            if class_hashes.contains_key(&item.address) {
+                data.push(Deployment {
+                    block_number: block,
+                    block_hash: block_with_txs.block_hash,
+                    timestamp: block_with_txs.timestamp,
+                    transaction_hash: None,
+                    deployer: None,
+                    contract_address: item.address,
+                    class_hash: item.class_hash,
+                    deployment_type: None,
+                });
+            }
+        }
+    }
+
+    Ok(data)
+}
+
+/// Classes declared in the chunk, read from state diffs and attributed to their declare
+/// transaction, with their definition when `bodies_dir` is set
+pub async fn fetch_classes(
+    client: &JsonRpcClient<HttpTransport>,
+    (block_start, block_end): (u64, u64),
+    step: u64,
+    bodies_dir: Option<&str>,
+    chunk_id: u16,
+) -> Result<Vec<DeclaredClass>, SerpicoError> {
+    let mut data = Vec::new();
+    for block in tqdm!(
+        (block_start..(block_end + 1)).step_by(step as usize),
+        desc = format!("block {} to {}", block_start, block_end),
+        position = chunk_id
+    ) {
+        let state_diff = fetch_state_diff(client, block).await?;
+        if state_diff.declared_classes.is_empty()
+            && state_diff.deprecated_declared_classes.is_empty()
+        {
+            continue;
+        }
+
+        let block_with_txs = fetch_block_with_txs(client, block).await?;
+        let declarations: HashMap<Felt, (Felt, Felt)> = block_with_txs
+            .transactions
+            .iter()
+            .filter_map(|tx| match tx {
+                Transaction::Declare(DeclareTransaction::V0(sub_tx)) => Some((
+                    sub_tx.class_hash,
+                    (sub_tx.transaction_hash, sub_tx.sender_address),
+                )),
+                Transaction::Declare(DeclareTransaction::V1(sub_tx)) => Some((
+                    sub_tx.class_hash,
+                    (sub_tx.transaction_hash, sub_tx.sender_address),
+                )),
+                Transaction::Declare(DeclareTransaction::V2(sub_tx)) => Some((
+                    sub_tx.class_hash,
+                    (sub_tx.transaction_hash, sub_tx.sender_address),
+                )),
+                Transaction::Declare(DeclareTransaction::V3(sub_tx)) => Some((
+                    sub_tx.class_hash,
+                    (sub_tx.transaction_hash, sub_tx.sender_address),
+                )),
+                _ => None,
+            })
+            .collect();
+
+        let classes = state_diff
+            .deprecated_declared_classes
+            .iter()
+            .map(|class_hash| (*class_hash, None, "cairo0"))
+            .chain(
+                state_diff
+                    .declared_classes
+                    .iter()
+                    .map(|item| (item.class_hash, Some(item.compiled_class_hash), "cairo1")),
+            );
+        for (class_hash, compiled_class_hash, cairo_version) in classes {
+            let body = match bodies_dir {
+                Some(bodies_dir) => fetch_class_body(client, block, class_hash, bodies_dir).await?,
+                None => None,
+            };
+            let declaration = declarations.get(&class_hash);
+            data.push(DeclaredClass {
+                block_number: block,
+                block_hash: block_with_txs.block_hash,
+                timestamp: block_with_txs.timestamp,
+                transaction_hash: declaration.map(|(transaction_hash, _)| *transaction_hash),
+                sender: declaration.map(|(_, sender)| *sender),
+                class_hash,
+                compiled_class_hash,
+                cairo_version,
+                body,
+            });
+        }
+    }
+
+    Ok(data)
+}
+
+/// Summary of a class definition, which is written once to `{bodies_dir}/0x{class_hash}.json`.
+///
+/// Class hashes commit to the definition, so a definition already on disk is read back
+/// instead of being fetched again.
+async fn fetch_class_body(
+    client: &JsonRpcClient<HttpTransport>,
+    block: u64,
+    class_hash: Felt,
+    bodies_dir: &str,
+) -> Result<Option<ClassBody>, SerpicoError> {
+    let body_file = format!("{}/0x{:x}.json", bodies_dir, class_hash);
+    let cached = if Path::new(&body_file).exists() {
+        let content = read_to_string(&body_file).map_err(SerpicoError::IoErr)?;
+        serde_json::from_str::<ContractClass>(&content).ok()
+    } else {
+        None
+    };
+    let class = match cached {
+        Some(class) => class,
+        None => {
+            let class = match rpc::get_class(client, BlockId::Number(block), class_hash).await {
+                Ok(class) => class,
+                // e.g. class not found
+                Err(ProviderError::StarknetError(_)) => return Ok(None),
+                Err(err) => return Err(SerpicoError::ClientErr(err)),
+            };
+            let content =
+                serde_json::to_string(&class).map_err(|e| SerpicoError::AbiErr(e.to_string()))?;
+            write(&body_file, content).map_err(SerpicoError::IoErr)?;
+            class
+        }
     };
 
-    let events = client
-        .get_events(filter, None, 1)
+    Ok(Some(match class {
+        ContractClass::Sierra(class) => ClassBody {
+            abi: class.abi,
+            entry_point_selectors: [
+                class.entry_points_by_type.constructor,
+                class.entry_points_by_type.external,
+                class.entry_points_by_type.l1_handler,
+            ]
+            .concat()
+            .iter()
+            .map(|entry_point| entry_point.selector)
+            .collect(),
+            sierra_program_size: Some(class.sierra_program.len() as u64),
+        },
+        ContractClass::Legacy(class) => ClassBody {
+            abi: serde_json::to_string(&class.abi.unwrap_or_default())
+                .map_err(|e| SerpicoError::AbiErr(e.to_string()))?,
+            entry_point_selectors: [
+                class.entry_points_by_type.constructor,
+                class.entry_points_by_type.external,
+                class.entry_points_by_type.l1_handler,
+            ]
+            .concat()
+            .iter()
+            .map(|entry_point| entry_point.selector)
+            .collect(),
+            sierra_program_size: None,
+        },
+    }))
+}
+
+/// L1 to L2 messages consumed by `L1Handler` transactions, and L2 to L1 messages sent by
+/// every transaction, read from their receipts
+pub async fn fetch_messages(
+    client: &JsonRpcClient<HttpTransport>,
+    (block_start, block_end): (u64, u64),
+    step: u64,
+    chunk_id: u16,
+) -> Result<Vec<Message>, SerpicoError> {
+    let mut data = Vec::new();
+    for block in tqdm!(
+        (block_start..(block_end + 1)).step_by(step as usize),
+        desc = format!("block {} to {}", block_start, block_end),
+        position = chunk_id
+    ) {
+        let block_with_txs = fetch_block_with_txs(client, block).await?;
+        for (transaction_index, tx) in block_with_txs.transactions.iter().enumerate() {
+            let transaction_hash = *tx.transaction_hash();
+            let message = |message_index: usize| Message {
+                block_number: block,
+                block_hash: block_with_txs.block_hash,
+                timestamp: block_with_txs.timestamp,
+                transaction_index: transaction_index as u64,
+                transaction_hash,
+                message_index: message_index as u64,
+                direction: "l2_to_l1",
+                from_address: Felt::ZERO,
+                to_address: Felt::ZERO,
+                selector: None,
+                payload: Vec::new(),
+                nonce: None,
+                message_hash: None,
+            };
+
+            if let Transaction::L1Handler(sub_tx) = tx {
+                if let Ok(msg) = sub_tx.parse_msg_to_l2() {
+                    data.push(Message {
+                        direction: "l1_to_l2",
+                        from_address: msg.from_address.clone().into(),
+                        to_address: msg.to_address,
+                        selector: Some(msg.selector),
+                        nonce: Some(msg.nonce),
+                        message_hash: Some(msg.hash().to_string()),
+                        payload: msg.payload,
+                        ..message(0)
+                    });
+                }
+            }
+
+            let receipt = fetch_receipt(client, transaction_hash).await?;
+            for (message_index, msg) in messages_sent(&receipt).iter().enumerate() {
+                data.push(Message {
+                    from_address: msg.from_address,
+                    to_address: msg.to_address,
+                    payload: msg.payload.clone(),
+                    ..message(message_index)
+                });
+            }
+        }
+    }
+
+    Ok(data)
+}
+
+/// Pending block with its transactions and events, along with its number.
+///
+/// The block is not sealed yet: it is numbered after its parent, its hash and state root are
+/// zero and its events have no block hash. Events of transactions added to the pending block after
+/// it was read are left out, so that the snapshot stays consistent.
+pub async fn fetch_pending(
+    client: &JsonRpcClient<HttpTransport>,
+    datasets: &[Datasets],
+    params: &FetchParams,
+) -> Result<(u64, Vec<(String, Data)>), SerpicoError> {
+    let pending = match rpc::get_block_with_txs(client, BlockId::Tag(BlockTag::Pending))
         .await
-        .map_err(SerpicoError::ClientErr)?;
+        .map_err(SerpicoError::ClientErr)?
+    {
+        MaybePendingBlockWithTxs::PendingBlock(b) => b,
+        // nodes without a pending block answer with the chain head
+        MaybePendingBlockWithTxs::Block(_) => {
+            return Err(SerpicoError::BlockParsingErr(
+                "the node does not serve a pending block".to_string(),
+            ))
+        }
+    };
+    let block_number =
+        match rpc::get_block_with_tx_hashes(client, BlockId::Hash(pending.parent_hash))
+            .await
+            .map_err(SerpicoError::ClientErr)?
+        {
+            MaybePendingBlockWithTxHashes::Block(parent) => parent.block_number + 1,
+            MaybePendingBlockWithTxHashes::PendingBlock(_) => {
+                return Err(SerpicoError::BlockParsingErr(
+                    "the parent of the pending block is pending too".to_string(),
+                ))
+            }
+        };
+    let transaction_indexes: HashMap<Felt, u64> = pending
+        .transactions
+        .iter()
+        .enumerate()
+        .map(|(index, tx)| (*tx.transaction_hash(), index as u64))
+        .collect();
+
+    let mut data = Vec::new();
+    if datasets.contains(&Datasets::Blocks) {
+        let l2_gas_price = match &params.l2_gas_transport {
+            Some(transport) => {
+                rpc::get_block_l2_gas_price(transport, BlockId::Tag(BlockTag::Pending))
+                    .await
+                    .map_err(SerpicoError::ClientErr)?
+            }
+            None => None,
+        };
+        let block = BlockWithTxHashes {
+            status: BlockStatus::Pending,
+            block_hash: Felt::ZERO,
+            parent_hash: pending.parent_hash,
+            block_number,
+            new_root: Felt::ZERO,
+            timestamp: pending.timestamp,
+            sequencer_address: pending.sequencer_address,
+            l1_gas_price: pending.l1_gas_price.clone(),
+            l1_data_gas_price: pending.l1_data_gas_price.clone(),
+            l1_da_mode: pending.l1_da_mode,
+            starknet_version: pending.starknet_version.clone(),
+            transactions: pending
+                .transactions
+                .iter()
+                .map(|tx| *tx.transaction_hash())
+                .collect(),
+        };
+        data.push((
+            Datasets::Blocks.to_name().to_string(),
+            Data::Blocks(vec![(block, l2_gas_price)]),
+        ));
+    }
+    if datasets.contains(&Datasets::Logs) {
+        let filter = EventFilter {
+            from_block: Some(BlockId::Tag(BlockTag::Pending)),
+            to_block: Some(BlockId::Tag(BlockTag::Pending)),
+            address: None,
+            keys: None,
+        };
+        let events: Vec<EmittedEvent> = fetch_events(client, filter)
+            .await?
+            .into_iter()
+            .filter(|event| transaction_indexes.contains_key(&event.transaction_hash))
+            .map(|event| EmittedEvent {
+                block_number: Some(block_number),
+                ..event
+            })
+            .collect();
+        let blocks = HashMap::from([(block_number, (pending.timestamp, transaction_indexes))]);
+        let mut logs = position_events(events, &blocks);
+        if let Some(contract) = params.contract {
+            logs.retain(|log| log.event.from_address == contract);
+        }
+        data.push((Datasets::Logs.tables(params).remove(0), Data::Logs(logs)));
+    }
+    if datasets.contains(&Datasets::Transactions) {
+        data.push((
+            Datasets::Transactions.to_name().to_string(),
+            Data::Transactions(
+                pending
+                    .transactions
+                    .into_iter()
+                    .enumerate()
+                    .map(|(transaction_index, tx)| BlockTx {
+                        tx,
+                        block_number,
+                        block_hash: Felt::ZERO,
+                        timestamp: pending.timestamp,
+                        transaction_index: transaction_index as u64,
+                    })
+                    .collect(),
+            ),
+        ));
+    }
+
+    Ok((block_number, data))
+}
 
-    Ok(Data::Logs(events.events))
+/// Aggregates of each block, computed from its transactions and their receipts
+pub async fn fetch_block_stats(
+    client: &JsonRpcClient<HttpTransport>,
+    (block_start, block_end): (u64, u64),
+    step: u64,
+    chunk_id: u16,
+) -> Result<Vec<BlockStats>, SerpicoError> {
+    let mut data = Vec::new();
+    for block in tqdm!(
+        (block_start..(block_end + 1)).step_by(step as usize),
+        desc = format!("block {} to {}", block_start, block_end),
+        position = chunk_id
+    ) {
+        let block_with_txs = fetch_block_with_txs(client, block).await?;
+        let mut stats = BlockStats {
+            block_number: block,
+            timestamp: block_with_txs.timestamp,
+            tx_count: block_with_txs.transactions.len() as u64,
+            tx_type_counts: HashMap::new(),
+            event_count: 0,
+            unique_senders: 0,
+            total_fee_wei: Felt::ZERO,
+            total_fee_fri: Felt::ZERO,
+            total_steps: 0,
+            total_l1_gas: 0,
+            total_l1_data_gas: 0,
+            reverted_tx_count: 0,
+        };
+        let mut senders = HashSet::new();
+        for tx in block_with_txs.transactions.iter() {
+            *stats.tx_type_counts.entry(tx_type_column(tx)).or_insert(0) += 1;
+            match tx {
+                Transaction::Invoke(InvokeTransaction::V1(sub_tx)) => {
+                    senders.insert(sub_tx.sender_address);
+                }
+                Transaction::Invoke(InvokeTransaction::V3(sub_tx)) => {
+                    senders.insert(sub_tx.sender_address);
+                }
+                Transaction::Declare(DeclareTransaction::V0(sub_tx)) => {
+                    senders.insert(sub_tx.sender_address);
+                }
+                Transaction::Declare(DeclareTransaction::V1(sub_tx)) => {
+                    senders.insert(sub_tx.sender_address);
+                }
+                Transaction::Declare(DeclareTransaction::V2(sub_tx)) => {
+                    senders.insert(sub_tx.sender_address);
+                }
+                Transaction::Declare(DeclareTransaction::V3(sub_tx)) => {
+                    senders.insert(sub_tx.sender_address);
+                }
+                _ => (),
+            }
+
+            let receipt = fetch_receipt(client, *tx.transaction_hash()).await?;
+            let (fee, resources, events) = match &receipt {
+                TransactionReceipt::Invoke(r) => (&r.actual_fee, &r.execution_resources, &r.events),
+                TransactionReceipt::L1Handler(r) => {
+                    (&r.actual_fee, &r.execution_resources, &r.events)
+                }
+                TransactionReceipt::Declare(r) => {
+                    (&r.actual_fee, &r.execution_resources, &r.events)
+                }
+                TransactionReceipt::Deploy(r) => (&r.actual_fee, &r.execution_resources, &r.events),
+                TransactionReceipt::DeployAccount(r) => {
+                    (&r.actual_fee, &r.execution_resources, &r.events)
+                }
+            };
+            match fee.unit {
+                PriceUnit::Wei => stats.total_fee_wei += fee.amount,
+                PriceUnit::Fri => stats.total_fee_fri += fee.amount,
+            }
+            stats.total_steps += resources.computation_resources.steps;
+            let data_availability = &resources.data_resources.data_availability;
+            stats.total_l1_gas += data_availability.l1_gas;
+            stats.total_l1_data_gas += data_availability.l1_data_gas;
+            stats.event_count += events.len() as u64;
+            if let ExecutionResult::Reverted { .. } = receipt.execution_result() {
+                stats.reverted_tx_count += 1;
+            }
+        }
+        stats.unique_senders = senders.len() as u64;
+        data.push(stats);
+    }
+
+    Ok(data)
+}
+
+/// Receipt of a transaction of an accepted block, which is still pending for a short while
+/// on some nodes
+async fn fetch_receipt(
+    client: &JsonRpcClient<HttpTransport>,
+    transaction_hash: Felt,
+) -> Result<TransactionReceipt, SerpicoError> {
+    let mut attempt = 0;
+    loop {
+        let receipt = rpc::get_transaction_receipt(client, transaction_hash)
+            .await
+            .map_err(SerpicoError::ClientErr)?;
+        match receipt.block {
+            ReceiptBlock::Block { .. } => return Ok(receipt.receipt),
+            ReceiptBlock::Pending => {
+                let err = SerpicoError::BlockParsingErr(format!(
+                    "receipt of transaction 0x{:x} is still pending",
+                    transaction_hash
+                ));
+                backoff(&mut attempt, err).await?
+            }
+        }
+    }
+}
+
+/// Events of a receipt, whatever the transaction type
+fn receipt_events(receipt: &TransactionReceipt) -> &[Event] {
+    match receipt {
+        TransactionReceipt::Invoke(receipt) => &receipt.events,
+        TransactionReceipt::L1Handler(receipt) => &receipt.events,
+        TransactionReceipt::Declare(receipt) => &receipt.events,
+        TransactionReceipt::Deploy(receipt) => &receipt.events,
+        TransactionReceipt::DeployAccount(receipt) => &receipt.events,
+    }
+}
+
+/// L2 to L1 messages of a receipt, whatever the transaction type
+fn messages_sent(receipt: &TransactionReceipt) -> &[MsgToL1] {
+    match receipt {
+        TransactionReceipt::Invoke(receipt) => &receipt.messages_sent,
+        TransactionReceipt::L1Handler(receipt) => &receipt.messages_sent,
+        TransactionReceipt::Declare(receipt) => &receipt.messages_sent,
+        TransactionReceipt::Deploy(receipt) => &receipt.messages_sent,
+        TransactionReceipt::DeployAccount(receipt) => &receipt.messages_sent,
+    }
+}
+
+/// Constructor invocations of a call tree, i.e. contracts deployed with `deploy_syscall`
+fn collect_constructors(
+    invocations: &[FunctionInvocation],
+    constructors: &mut Vec<FunctionInvocation>,
+) {
+    for invocation in invocations {
+        if invocation.entry_point_type == EntryPointType::Constructor {
+            constructors.push(invocation.clone());
+        }
+        collect_constructors(&invocation.calls, constructors);
+    }
+}
+
+async fn fetch_state_diff(
+    client: &JsonRpcClient<HttpTransport>,
+    block: u64,
+) -> Result<StateDiff, SerpicoError> {
+    match rpc::get_state_update(client, BlockId::Number(block))
+        .await
+        .map_err(SerpicoError::ClientErr)?
+    {
+        MaybePendingStateUpdate::Update(update) => Ok(update.state_diff),
+        MaybePendingStateUpdate::PendingUpdate(_) => Err(not_produced(block)),
+    }
+}
+
+async fn fetch_block_with_txs(
+    client: &JsonRpcClient<HttpTransport>,
+    block: u64,
+) -> Result<BlockWithTxs, SerpicoError> {
+    match rpc::get_block_with_txs(client, BlockId::Number(block))
+        .await
+        .map_err(SerpicoError::ClientErr)?
+    {
+        MaybePendingBlockWithTxs::Block(b) => Ok(b),
+        MaybePendingBlockWithTxs::PendingBlock(_) => Err(not_produced(block)),
+    }
+}
+
+async fn fetch_block_with_tx_hashes(
+    client: &JsonRpcClient<HttpTransport>,
+    block: u64,
+) -> Result<BlockWithTxHashes, SerpicoError> {
+    match rpc::get_block_with_tx_hashes(client, BlockId::Number(block))
+        .await
+        .map_err(SerpicoError::ClientErr)?
+    {
+        MaybePendingBlockWithTxHashes::Block(b) => Ok(b),
+        MaybePendingBlockWithTxHashes::PendingBlock(_) => Err(not_produced(block)),
+    }
+}
+
+async fn fetch_block_with_receipts(
+    client: &JsonRpcClient<HttpTransport>,
+    block: u64,
+) -> Result<BlockWithReceipts, SerpicoError> {
+    match rpc::get_block_with_receipts(client, BlockId::Number(block))
+        .await
+        .map_err(SerpicoError::ClientErr)?
+    {
+        MaybePendingBlockWithReceipts::Block(b) => Ok(b),
+        MaybePendingBlockWithReceipts::PendingBlock(_) => Err(not_produced(block)),
+    }
+}
+
+/// Error of a block number answered with the pending block, which nodes do when the number is
+/// the next one
+fn not_produced(block: u64) -> SerpicoError {
+    SerpicoError::BlockParsingErr(format!("block {} is not produced yet", block))
+}
+
+/// Traces of the block transactions, empty when the node does not serve traces
+async fn fetch_block_traces(
+    client: &JsonRpcClient<HttpTransport>,
+    block: u64,
+) -> Result<Vec<TransactionTraceWithHash>, SerpicoError> {
+    match rpc::trace_block_transactions(client, BlockId::Number(block)).await {
+        Ok(traces) => Ok(traces),
+        Err(err) if is_trace_unavailable(&err) => Ok(Vec::new()),
+        Err(err) => Err(SerpicoError::ClientErr(err)),
+    }
 }
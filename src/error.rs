@@ -1,14 +1,44 @@
 use csv::Error;
 use starknet::providers::ProviderError;
+use std::fmt;
 use std::io;
 use std::num::ParseIntError;
 use url::ParseError;
 
 #[derive(Debug)]
+#[allow(clippy::enum_variant_names)]
 pub enum SerpicoError {
     UrlParsingErr(ParseError),
     IntParsingErr(ParseIntError),
+    BlockParsingErr(String),
+    DatasetParsingErr(String),
+    DateParsingErr(String),
+    PartitionParsingErr(String),
+    TemplateParsingErr(String),
+    AddressParsingErr(String),
+    AbiErr(String),
     ClientErr(ProviderError),
     WriterErr(Error),
     IoErr(io::Error),
 }
+
+impl fmt::Display for SerpicoError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            SerpicoError::UrlParsingErr(err) => write!(f, "invalid RPC url: {}", err),
+            SerpicoError::IntParsingErr(err) => write!(f, "invalid number: {}", err),
+            SerpicoError::BlockParsingErr(msg)
+            | SerpicoError::DatasetParsingErr(msg)
+            | SerpicoError::DateParsingErr(msg)
+            | SerpicoError::PartitionParsingErr(msg)
+            | SerpicoError::TemplateParsingErr(msg)
+            | SerpicoError::AddressParsingErr(msg)
+            | SerpicoError::AbiErr(msg) => write!(f, "{}", msg),
+            SerpicoError::ClientErr(err) => write!(f, "RPC error: {}", err),
+            SerpicoError::WriterErr(err) => write!(f, "CSV error: {}", err),
+            SerpicoError::IoErr(err) => write!(f, "IO error: {}", err),
+        }
+    }
+}
+
+impl std::error::Error for SerpicoError {}
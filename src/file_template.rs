@@ -0,0 +1,230 @@
+use crate::SerpicoError;
+use starknet::core::types::Felt;
+use starknet::core::utils::parse_cairo_short_string;
+
+pub const DEFAULT_FILE_TEMPLATE: &str = "{dataset}_from_{start}_to_{end}.{export_type}";
+
+/// Output file name such as `{network}__{dataset}__{start:08}_to_{end:08}.parquet`.
+///
+/// Every placeholder except the chunk bounds is resolved when the template is built,
+/// so a file name can be parsed back into the block chunk it holds.
+#[derive(Debug, Clone)]
+pub struct FileTemplate {
+    parts: Vec<TemplatePart>,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+enum TemplatePart {
+    Literal(String),
+    // zero padded width of the block number
+    Start(usize),
+    End(usize),
+}
+
+impl FileTemplate {
+    pub fn new(
+        template: &str,
+        dataset: &str,
+        network: &str,
+        export_type: &str,
+        label: &str,
+    ) -> Result<Self, SerpicoError> {
+        let invalid = |reason: &str| {
+            SerpicoError::TemplateParsingErr(format!("template `{}` {}", template, reason))
+        };
+        if template.contains('/') {
+            return Err(invalid(
+                "cannot contain directories, use --partition instead",
+            ));
+        }
+
+        let mut parts = Vec::new();
+        let mut with_dataset = false;
+        let mut rest = template;
+        while let Some(open) = rest.find('{') {
+            push_literal(&mut parts, &rest[..open]);
+            let close = rest[open..]
+                .find('}')
+                .ok_or_else(|| invalid("has an unclosed placeholder"))?
+                + open;
+            let placeholder = &rest[open + 1..close];
+            let (name, width) = match placeholder.split_once(':') {
+                Some((name, width)) => match width.parse::<usize>() {
+                    Ok(width) if name == "start" || name == "end" => (name, width),
+                    _ => return Err(invalid("only supports widths like {start:08}")),
+                },
+                None => (placeholder, 0),
+            };
+            match name {
+                "start" => parts.push(TemplatePart::Start(width)),
+                "end" => parts.push(TemplatePart::End(width)),
+                "dataset" => {
+                    with_dataset = true;
+                    push_literal(&mut parts, dataset)
+                }
+                "network" => push_literal(&mut parts, network),
+                "export_type" => push_literal(&mut parts, export_type),
+                "label" => push_literal(&mut parts, label),
+                _ => {
+                    return Err(invalid(&format!(
+                        "has unknown placeholder {{{}}}, expected dataset, network, start, end, export_type or label",
+                        name
+                    )))
+                }
+            }
+            rest = &rest[close + 1..];
+        }
+        push_literal(&mut parts, rest);
+        // several datasets share the output directory
+        if !with_dataset {
+            return Err(invalid("must contain {dataset}"));
+        }
+
+        let starts = parts
+            .iter()
+            .filter(|part| matches!(part, TemplatePart::Start(_)))
+            .count();
+        let ends = parts
+            .iter()
+            .filter(|part| matches!(part, TemplatePart::End(_)))
+            .count();
+        if starts != 1 || ends != 1 {
+            return Err(invalid("must contain {start} and {end} exactly once"));
+        }
+        if parts.windows(2).all(|pair| {
+            matches!(pair[0], TemplatePart::Literal(_))
+                || matches!(pair[1], TemplatePart::Literal(_))
+        }) {
+            Ok(FileTemplate { parts })
+        } else {
+            Err(invalid("needs a separator between {start} and {end}"))
+        }
+    }
+
+    /// File name of the chunk `(start, end)`
+    pub fn render(&self, start: u64, end: u64) -> String {
+        let mut file_name = String::new();
+        for part in self.parts.iter() {
+            match part {
+                TemplatePart::Literal(literal) => file_name.push_str(literal),
+                TemplatePart::Start(width) => {
+                    file_name.push_str(&format!("{:0width$}", start, width = width))
+                }
+                TemplatePart::End(width) => {
+                    file_name.push_str(&format!("{:0width$}", end, width = width))
+                }
+            }
+        }
+        file_name
+    }
+
+    /// Chunk held by a file, if its name matches the template
+    pub fn parse(&self, file_name: &str) -> Option<(u64, u64)> {
+        let mut rest = file_name;
+        let mut start = None;
+        let mut end = None;
+        for part in self.parts.iter() {
+            match part {
+                TemplatePart::Literal(literal) => rest = rest.strip_prefix(literal.as_str())?,
+                TemplatePart::Start(_) | TemplatePart::End(_) => {
+                    let digits = rest
+                        .find(|c: char| !c.is_ascii_digit())
+                        .unwrap_or(rest.len());
+                    let block = rest[..digits].parse::<u64>().ok()?;
+                    rest = &rest[digits..];
+                    if let TemplatePart::Start(_) = part {
+                        start = Some(block);
+                    } else {
+                        end = Some(block);
+                    }
+                }
+            }
+        }
+
+        if rest.is_empty() {
+            Some((start?, end?))
+        } else {
+            None
+        }
+    }
+}
+
+fn push_literal(parts: &mut Vec<TemplatePart>, literal: &str) {
+    if literal.is_empty() {
+        return;
+    }
+    match parts.last_mut() {
+        Some(TemplatePart::Literal(previous)) => previous.push_str(literal),
+        _ => parts.push(TemplatePart::Literal(literal.to_string())),
+    }
+}
+
+/// Readable network name of a chain id, e.g. `mainnet` for `SN_MAIN`
+pub fn network_name(chain_id: Felt) -> String {
+    match parse_cairo_short_string(&chain_id) {
+        Ok(name) => match name.as_str() {
+            "SN_MAIN" => "mainnet".to_string(),
+            "SN_GOERLI" => "goerli".to_string(),
+            "SN_SEPOLIA" => "sepolia".to_string(),
+            _ => name.to_lowercase(),
+        },
+        Err(_) => format!("0x{:x}", chain_id),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn template(template: &str) -> Result<FileTemplate, SerpicoError> {
+        FileTemplate::new(template, "blocks", "mainnet", "parquet", "run1")
+    }
+
+    #[test]
+    fn renders_and_parses_default_template() {
+        let file_template = template(DEFAULT_FILE_TEMPLATE).unwrap();
+        let file_name = file_template.render(100, 199);
+        assert_eq!(file_name, "blocks_from_100_to_199.parquet");
+        assert_eq!(file_template.parse(&file_name), Some((100, 199)));
+    }
+
+    #[test]
+    fn renders_and_parses_padded_template() {
+        let file_template =
+            template("{network}__{dataset}__{label}__{start:08}_to_{end:08}.{export_type}")
+                .unwrap();
+        let file_name = file_template.render(100, 199);
+        assert_eq!(
+            file_name,
+            "mainnet__blocks__run1__00000100_to_00000199.parquet"
+        );
+        assert_eq!(file_template.parse(&file_name), Some((100, 199)));
+    }
+
+    #[test]
+    fn ignores_other_files() {
+        let file_template = template(DEFAULT_FILE_TEMPLATE).unwrap();
+        assert_eq!(
+            file_template.parse("transactions_from_100_to_199.parquet"),
+            None
+        );
+        assert_eq!(
+            file_template.parse("blocks_every_10_from_100_to_199.parquet"),
+            None
+        );
+        assert_eq!(file_template.parse("blocks_from_100_to_199.csv"), None);
+        assert_eq!(file_template.parse("blocks_from_100_to_.parquet"), None);
+    }
+
+    #[test]
+    fn rejects_invalid_templates() {
+        assert!(template("from_{start}_to_{end}.csv").is_err());
+        assert!(template("{dataset}_{start}.csv").is_err());
+        assert!(template("{dataset}_{start}_{start}_{end}.csv").is_err());
+        assert!(template("{dataset}_{start}{end}.csv").is_err());
+        assert!(template("{dataset}/{start}_{end}.csv").is_err());
+        assert!(template("{dataset}_{start:x}_{end}.csv").is_err());
+        assert!(template("{dataset}_{chain}_{start}_{end}.csv").is_err());
+        assert!(template("{dataset}_{start}_{end").is_err());
+    }
+}
@@ -0,0 +1,305 @@
+use crate::rpc::get_block_with_tx_hashes;
+use crate::SerpicoError;
+use starknet::core::types::{
+    BlockId, BlockStatus, Felt, MaybePendingBlockWithTxHashes, StarknetError,
+};
+use starknet::providers::jsonrpc::HttpTransport;
+use starknet::providers::{JsonRpcClient, ProviderError};
+use std::collections::BTreeMap;
+use std::path::Path;
+
+/// Latest block accepted on L1, if any.
+///
+/// Blocks are proven in order, so the blocks accepted on L1 are a prefix of the chain and
+/// the last one is found by binary search on block statuses.
+pub async fn last_block_on_l1(
+    client: &JsonRpcClient<HttpTransport>,
+    last_block: u64,
+) -> Result<Option<u64>, SerpicoError> {
+    let mut low = 0;
+    let mut high = last_block + 1;
+    while low < high {
+        let middle = low + (high - low) / 2;
+        if block_status(client, middle).await? == BlockStatus::AcceptedOnL1 {
+            low = middle + 1;
+        } else {
+            high = middle;
+        }
+    }
+
+    Ok(low.checked_sub(1))
+}
+
+async fn block_status(
+    client: &JsonRpcClient<HttpTransport>,
+    block: u64,
+) -> Result<BlockStatus, SerpicoError> {
+    match get_block_with_tx_hashes(client, BlockId::Number(block))
+        .await
+        .map_err(SerpicoError::ClientErr)?
+    {
+        MaybePendingBlockWithTxHashes::Block(b) => Ok(b.status),
+        MaybePendingBlockWithTxHashes::PendingBlock(_) => Ok(BlockStatus::Pending),
+    }
+}
+
+/// Hash of a block, none when the chain does not reach it anymore
+pub async fn block_hash(
+    client: &JsonRpcClient<HttpTransport>,
+    block: u64,
+) -> Result<Option<Felt>, SerpicoError> {
+    match get_block_with_tx_hashes(client, BlockId::Number(block)).await {
+        Ok(MaybePendingBlockWithTxHashes::Block(b)) => Ok(Some(b.block_hash)),
+        Ok(MaybePendingBlockWithTxHashes::PendingBlock(_)) => Ok(None),
+        Err(ProviderError::StarknetError(StarknetError::BlockNotFound)) => Ok(None),
+        Err(err) => Err(SerpicoError::ClientErr(err)),
+    }
+}
+
+/// Hash of the last block of an exported chunk, read before its data
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum ChunkHash {
+    // chunks recorded before hashes were, they are not checked
+    Unknown,
+    Fetched(Felt),
+    // the block changed since the chunk was exported, which has to be fetched again
+    Reorged,
+}
+
+/// Chunks exported while some of their blocks were only accepted on L2, by table.
+///
+/// They are kept in a CSV file of the output directory so that a later run with
+/// `--refresh-finality` exports them again once they are accepted on L1. The hash of the
+/// last block of each chunk is kept too: when it changes, the chunk was reorganized and is
+/// exported again by the next run requesting its table.
+pub struct L2Chunks {
+    file: String,
+    chunks: BTreeMap<String, BTreeMap<(u64, u64), ChunkHash>>,
+}
+
+impl L2Chunks {
+    pub fn load(file: String) -> Result<Self, SerpicoError> {
+        let mut chunks: BTreeMap<String, BTreeMap<(u64, u64), ChunkHash>> = BTreeMap::new();
+        if Path::new(file.as_str()).exists() {
+            let mut reader =
+                csv::Reader::from_path(file.as_str()).map_err(SerpicoError::WriterErr)?;
+            for record in reader.records() {
+                let record = record.map_err(SerpicoError::WriterErr)?;
+                let start = record[1]
+                    .parse::<u64>()
+                    .map_err(SerpicoError::IntParsingErr)?;
+                let end = record[2]
+                    .parse::<u64>()
+                    .map_err(SerpicoError::IntParsingErr)?;
+                let hash = match record.get(3).unwrap_or_default() {
+                    "" => ChunkHash::Unknown,
+                    "reorged" => ChunkHash::Reorged,
+                    hash => ChunkHash::Fetched(Felt::from_hex(hash).map_err(|_| {
+                        SerpicoError::BlockParsingErr(format!("`{}` is not a block hash", hash))
+                    })?),
+                };
+                chunks
+                    .entry(record[0].to_string())
+                    .or_default()
+                    .insert((start, end), hash);
+            }
+        }
+        Ok(L2Chunks { file, chunks })
+    }
+
+    /// Chunks of a table which are now entirely accepted on L1
+    pub fn proven(&self, table: &str, last_block_on_l1: Option<u64>) -> Vec<(u64, u64)> {
+        let Some(last_block_on_l1) = last_block_on_l1 else {
+            return Vec::new();
+        };
+        self.chunks
+            .get(table)
+            .map(|chunks| {
+                chunks
+                    .keys()
+                    .copied()
+                    .filter(|(_, end)| *end <= last_block_on_l1)
+                    .collect()
+            })
+            .unwrap_or_default()
+    }
+
+    /// Chunks of a table whose blocks were reorganized since they were exported
+    pub fn reorged(&self, table: &str) -> Vec<(u64, u64)> {
+        self.chunks
+            .get(table)
+            .map(|chunks| {
+                chunks
+                    .iter()
+                    .filter(|(_, hash)| **hash == ChunkHash::Reorged)
+                    .map(|(chunk, _)| *chunk)
+                    .collect()
+            })
+            .unwrap_or_default()
+    }
+
+    /// Compares the hash of the last block of every chunk with the chain, and marks the chunks
+    /// from the first changed one onwards as reorganized, whatever their table.
+    ///
+    /// Blocks between the last unchanged chunk and the first changed one are not recorded, so
+    /// the fork is somewhere in between and the logged depth is an upper bound.
+    pub async fn detect_reorgs(
+        &mut self,
+        client: &JsonRpcClient<HttpTransport>,
+        last_block: u64,
+        last_block_on_l1: Option<u64>,
+    ) -> Result<(), SerpicoError> {
+        let mut hashes = BTreeMap::new();
+        for chunks in self.chunks.values() {
+            for ((_, end), hash) in chunks {
+                if let ChunkHash::Fetched(hash) = hash {
+                    hashes.insert(*end, *hash);
+                }
+            }
+        }
+
+        // blocks accepted on L1 are final
+        let mut last_unchanged = last_block_on_l1;
+        let mut first_changed = None;
+        for (block, hash) in hashes {
+            if block_hash(client, block).await? == Some(hash) {
+                last_unchanged = last_unchanged.max(Some(block));
+            } else {
+                first_changed = Some(block);
+                break;
+            }
+        }
+        let Some(first_changed) = first_changed else {
+            return Ok(());
+        };
+
+        let mut reorged_chunks = 0;
+        for chunks in self.chunks.values_mut() {
+            for ((_, end), hash) in chunks.iter_mut() {
+                if *end >= first_changed && *hash != ChunkHash::Reorged {
+                    *hash = ChunkHash::Reorged;
+                    reorged_chunks += 1;
+                }
+            }
+        }
+        let fork_start = last_unchanged.map_or(0, |block| block + 1);
+        println!(
+            "Reorg detected: block {} changed since it was exported, the fork is between blocks {} and {} ({} blocks deep at most), {} chunks invalidated",
+            first_changed,
+            fork_start,
+            first_changed,
+            (last_block + 1).saturating_sub(fork_start),
+            reorged_chunks
+        );
+        Ok(())
+    }
+
+    /// Records a written chunk with the hash of its last block, the chunk is forgotten once it
+    /// is entirely accepted on L1
+    pub fn update(
+        &mut self,
+        table: &str,
+        chunk: (u64, u64),
+        end_block_hash: Option<Felt>,
+        last_block_on_l1: Option<u64>,
+    ) {
+        let chunks = self.chunks.entry(table.to_string()).or_default();
+        if last_block_on_l1.is_none_or(|block| chunk.1 > block) {
+            chunks.insert(
+                chunk,
+                end_block_hash.map_or(ChunkHash::Unknown, ChunkHash::Fetched),
+            );
+        } else {
+            chunks.remove(&chunk);
+        }
+    }
+
+    pub fn save(&self) -> Result<(), SerpicoError> {
+        let mut writer =
+            csv::Writer::from_path(self.file.as_str()).map_err(SerpicoError::WriterErr)?;
+        writer
+            .write_record(["table", "start_block", "end_block", "end_block_hash"])
+            .map_err(SerpicoError::WriterErr)?;
+        for (table, chunks) in self.chunks.iter() {
+            for ((start, end), hash) in chunks {
+                let hash = match hash {
+                    ChunkHash::Unknown => String::new(),
+                    ChunkHash::Fetched(hash) => format!("0x{:x}", hash),
+                    ChunkHash::Reorged => "reorged".to_string(),
+                };
+                writer
+                    .write_record([table.clone(), start.to_string(), end.to_string(), hash])
+                    .map_err(SerpicoError::WriterErr)?;
+            }
+        }
+        writer.flush().map_err(SerpicoError::IoErr)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::fs::remove_file;
+
+    fn ledger_file(name: &str) -> String {
+        let file = std::env::temp_dir().join(format!("serpico_l2_chunks_{}.csv", name));
+        let _ = remove_file(&file);
+        file.to_str().unwrap().to_string()
+    }
+
+    #[test]
+    fn saves_and_loads_chunks() {
+        let file = ledger_file("round_trip");
+        let mut l2_chunks = L2Chunks::load(file.clone()).unwrap();
+        l2_chunks.update("blocks", (100, 199), Some(Felt::from(0xabcu64)), Some(50));
+        l2_chunks.update("blocks", (200, 299), None, Some(50));
+        l2_chunks.update("logs", (100, 199), Some(Felt::from(0xabcu64)), Some(50));
+        l2_chunks
+            .chunks
+            .get_mut("logs")
+            .unwrap()
+            .insert((200, 299), ChunkHash::Reorged);
+        l2_chunks.save().unwrap();
+
+        let loaded = L2Chunks::load(file.clone()).unwrap();
+        assert_eq!(loaded.chunks, l2_chunks.chunks);
+        assert_eq!(loaded.reorged("logs"), vec![(200, 299)]);
+        assert_eq!(loaded.reorged("blocks"), vec![]);
+        remove_file(file).unwrap();
+    }
+
+    #[test]
+    fn lists_chunks_accepted_on_l1() {
+        let mut l2_chunks = L2Chunks::load(ledger_file("proven")).unwrap();
+        l2_chunks.update("blocks", (100, 199), None, Some(50));
+        l2_chunks.update("blocks", (200, 299), None, Some(50));
+        assert_eq!(l2_chunks.proven("blocks", None), vec![]);
+        assert_eq!(l2_chunks.proven("blocks", Some(250)), vec![(100, 199)]);
+        assert_eq!(l2_chunks.proven("logs", Some(250)), vec![]);
+    }
+
+    #[test]
+    fn forgets_chunks_exported_again_once_accepted_on_l1() {
+        let mut l2_chunks = L2Chunks::load(ledger_file("update")).unwrap();
+        l2_chunks.update("blocks", (100, 199), None, Some(50));
+        l2_chunks
+            .chunks
+            .get_mut("blocks")
+            .unwrap()
+            .insert((200, 299), ChunkHash::Reorged);
+
+        // a reorged chunk exported again gets the hash of its new last block
+        l2_chunks.update("blocks", (200, 299), Some(Felt::ONE), Some(150));
+        assert_eq!(l2_chunks.reorged("blocks"), vec![]);
+        assert_eq!(
+            l2_chunks.chunks["blocks"][&(200, 299)],
+            ChunkHash::Fetched(Felt::ONE)
+        );
+
+        l2_chunks.update("blocks", (100, 199), None, Some(250));
+        assert_eq!(
+            l2_chunks.chunks["blocks"].keys().collect::<Vec<_>>(),
+            vec![&(200, 299)]
+        );
+    }
+}
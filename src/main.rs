@@ -1,32 +1,61 @@
-use clap::Parser;
+use chrono::Utc;
+use clap::{ArgGroup, Parser};
 use polars::frame::DataFrame;
 use polars::prelude::*;
 use starknet::core::types::{
-    BlockStatus, BlockWithTxHashes, EmittedEvent, InvokeTransaction, Transaction,
+    BlockWithTxHashes, EmittedEvent, InvokeTransaction, ResourcePrice, Transaction,
 };
-use starknet::core::types::{DeclareTransaction, DeployAccountTransaction};
+use starknet::core::types::{
+    DataAvailabilityMode, DeclareTransaction, DeployAccountTransaction, Felt, ResourceBoundsMapping,
+};
+use starknet::core::utils::get_contract_address;
 use starknet::providers::jsonrpc::HttpTransport;
 use starknet::providers::Url;
 use starknet::providers::{JsonRpcClient, Provider};
-use std::collections::{BTreeMap, HashSet};
-use std::fs::{read_dir, File};
-use std::sync::Arc;
+use std::collections::{BTreeMap, BTreeSet, HashMap};
+use std::fs::{create_dir_all, remove_file, File};
+use std::io;
+use std::path::Path;
+use std::sync::{Arc, Mutex};
 use tokio::sync::Semaphore;
 
 mod cli_parser;
-use cli_parser::parse_blocks;
+use cli_parser::{
+    parse_address, parse_address_pair, parse_blocks, parse_datasets, parse_dates, parse_finality,
+    parse_partition, parse_storage_target, parse_targets, parse_timestamps, BlockRange, Finality,
+    Partition,
+};
 
 mod data_fetcher;
-use data_fetcher::fetch_data;
+use data_fetcher::{fetch_data, fetch_pending, FetchParams};
+
+mod transfers;
+
+mod rpc;
+
+mod abi;
+use abi::{load_abi, AbiDecoder, AbiValue, ValueKind};
 
 mod utils;
-use utils::split_block_chunks;
+use utils::{
+    fingerprint, group_by, remove_chunk_files, scan_chunks, split_block_chunks, CHUNK_MARKERS_DIR,
+};
+
+mod timestamp_resolver;
+use timestamp_resolver::resolve_timestamps;
+
+mod finality;
+use finality::{block_hash, last_block_on_l1, L2Chunks};
+
+mod file_template;
+use file_template::{network_name, FileTemplate, DEFAULT_FILE_TEMPLATE};
 
 mod error;
 use error::SerpicoError;
 
 #[derive(Debug, Parser)]
 #[command(version, about, long_about=None)]
+#[command(group(ArgGroup::new("interval").required(true).args(["blocks", "timestamps", "dates", "pending"])))]
 struct Cli {
     // RPC API Provider
     #[arg(short, long)]
@@ -34,9 +63,29 @@ struct Cli {
 
     // Block interval to use
     #[arg(short, long)]
-    blocks: String,
+    blocks: Option<String>,
+
+    // Unix timestamp interval to use, end excluded
+    #[arg(long)]
+    timestamps: Option<String>,
+
+    // UTC date interval to use (YYYY-MM-DD), end excluded
+    #[arg(long)]
+    dates: Option<String>,
+
+    // Capture the pending block into <path>/pending snapshot files instead of a block interval
+    #[arg(long, default_value_t = false)]
+    pending: bool,
+
+    // Only export blocks with this status: l2 (accepted on L2) or l1 (accepted on L1)
+    #[arg(long, default_value_t = String::from("l2"))]
+    finality: String,
 
-    // Dataset to fetch
+    // Export again the chunks written while only accepted on L2, once accepted on L1
+    #[arg(long, default_value_t = false)]
+    refresh_finality: bool,
+
+    // Datasets to fetch, comma separated
     #[arg(short, long)]
     dataset: String,
 
@@ -55,42 +104,586 @@ struct Cli {
 
     #[arg(long, default_value_t = 10000)]
     chunk_size: u64,
+
+    // Output layout: none, date or block:<bucket size>
+    #[arg(long, default_value_t = String::from("none"))]
+    partition: String,
+
+    // Output file name, placeholders are {dataset}, {network}, {start}, {end}, {export_type}
+    // and {label}, block numbers can be zero padded with {start:08}
+    #[arg(long, default_value_t = String::from(DEFAULT_FILE_TEMPLATE))]
+    file_template: String,
+
+    // Value of the {label} placeholder
+    #[arg(long, default_value_t = String::new())]
+    label: String,
+
+    // Number of event keys exported as key0, key1... columns
+    #[arg(long, default_value_t = 0)]
+    flat_keys: usize,
+
+    // Number of event data felts exported as data0, data1... columns
+    #[arg(long, default_value_t = 0)]
+    flat_data: usize,
+
+    // Only fetch events emitted by this contract, its class ABI decodes decoded_logs
+    #[arg(long)]
+    contract: Option<String>,
+
+    // Local ABI JSON file used by decoded_logs instead of the class of --contract
+    #[arg(long)]
+    abi: Option<String>,
+
+    // Call targets, comma separated <token>:<holder> pairs for balances,
+    // <contract>:<storage key> pairs for storage or account addresses for nonces
+    #[arg(long)]
+    targets: Option<String>,
+
+    // File holding one call target per line, in the same format as --targets
+    #[arg(long)]
+    targets_file: Option<String>,
+
+    // Fetch the definition of declared classes, stored once in <path>/class_bodies
+    #[arg(long, default_value_t = false)]
+    class_bodies: bool,
 }
 
-#[derive(Debug, Clone, Copy)]
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, PartialOrd, Ord)]
 enum Datasets {
     Blocks,
     Transactions,
     Logs,
+    DecodedLogs,
+    Calls,
+    Erc20Transfers,
+    Erc721Transfers,
+    Erc1155Transfers,
+    Balances,
+    Storage,
+    Nonces,
+    Contracts,
+    Classes,
+    Messages,
+    BlockStats,
     // Traces
-    // Transactions
-    None,
 }
 
 impl Datasets {
+    pub const ALL: [Datasets; 15] = [
+        Datasets::Blocks,
+        Datasets::Transactions,
+        Datasets::Logs,
+        Datasets::DecodedLogs,
+        Datasets::Calls,
+        Datasets::Erc20Transfers,
+        Datasets::Erc721Transfers,
+        Datasets::Erc1155Transfers,
+        Datasets::Balances,
+        Datasets::Storage,
+        Datasets::Nonces,
+        Datasets::Contracts,
+        Datasets::Classes,
+        Datasets::Messages,
+        Datasets::BlockStats,
+    ];
+
     pub fn to_name(self) -> &'static str {
         match self {
             Datasets::Blocks => "blocks",
             Datasets::Transactions => "transactions",
             Datasets::Logs => "logs",
-            Datasets::None => "",
+            Datasets::DecodedLogs => "decoded_logs",
+            Datasets::Calls => "calls",
+            Datasets::Erc20Transfers => "erc20_transfers",
+            Datasets::Erc721Transfers => "erc721_transfers",
+            Datasets::Erc1155Transfers => "erc1155_transfers",
+            Datasets::Balances => "balances",
+            Datasets::Storage => "storage",
+            Datasets::Nonces => "nonces",
+            Datasets::Contracts => "contracts",
+            Datasets::Classes => "classes",
+            Datasets::Messages => "messages",
+            Datasets::BlockStats => "block_stats",
         }
     }
+
+    pub fn from_name(name: &str) -> Option<Datasets> {
+        match name {
+            "blocks" | "block" => Some(Datasets::Blocks),
+            "transactions" | "transaction" => Some(Datasets::Transactions),
+            "logs" | "events" | "log" => Some(Datasets::Logs),
+            "decoded_logs" | "decoded_events" => Some(Datasets::DecodedLogs),
+            "calls" | "call" => Some(Datasets::Calls),
+            "erc20_transfers" | "erc20" => Some(Datasets::Erc20Transfers),
+            "erc721_transfers" | "erc721" => Some(Datasets::Erc721Transfers),
+            "erc1155_transfers" | "erc1155" => Some(Datasets::Erc1155Transfers),
+            "balances" | "balance" => Some(Datasets::Balances),
+            "storage" => Some(Datasets::Storage),
+            "nonces" | "nonce" => Some(Datasets::Nonces),
+            "contracts" | "contract" | "deployments" => Some(Datasets::Contracts),
+            "classes" | "class" => Some(Datasets::Classes),
+            "messages" | "message" => Some(Datasets::Messages),
+            "block_stats" => Some(Datasets::BlockStats),
+            _ => None,
+        }
+    }
+
+    /// Output tables of a dataset, decoded logs have one table per ABI event named after its
+    /// definition, e.g. `decoded_logs_Transfer_1a2b3c4d`, and balances and storage are named after their call
+    /// targets, e.g. `balances_1a2b3c4d`, so that other targets are fetched again. Classes
+    /// with their definitions go to `classes_with_bodies`, which chunks exported without
+    /// them do not fill.
+    pub fn tables(self, params: &FetchParams) -> Vec<String> {
+        // tables restricted to `--contract` are named after it, they only hold a subset of rows
+        let name = match (self, params.contract) {
+            (
+                Datasets::Logs
+                | Datasets::DecodedLogs
+                | Datasets::Calls
+                | Datasets::Erc20Transfers
+                | Datasets::Erc721Transfers
+                | Datasets::Erc1155Transfers,
+                Some(contract),
+            ) => format!("{}_{}", self.to_name(), fingerprint(&[contract])),
+            _ => self.to_name().to_string(),
+        };
+        match (self, params.abi_decoder.as_ref()) {
+            (Datasets::Balances | Datasets::Storage | Datasets::Nonces, _) => {
+                let felts: Vec<Felt> = match self {
+                    Datasets::Balances => params
+                        .balance_targets
+                        .iter()
+                        .flat_map(|(token, holder)| [*token, *holder])
+                        .collect(),
+                    Datasets::Storage => params
+                        .storage_targets
+                        .iter()
+                        .flat_map(|(contract, key)| [*contract, *key])
+                        .collect(),
+                    Datasets::Nonces => params.nonce_targets.clone(),
+                    _ => unreachable!("only target datasets are named after their targets"),
+                };
+                vec![format!("{}_{}", self.to_name(), fingerprint(&felts))]
+            }
+            (Datasets::DecodedLogs, Some(abi_decoder)) => abi_decoder
+                .events()
+                .iter()
+                .map(|event| {
+                    format!(
+                        "{}_{}_{}",
+                        name,
+                        event.name,
+                        fingerprint(&[event.definition_hash()])
+                    )
+                })
+                .collect(),
+            (Datasets::Classes, _) if params.class_bodies_dir.is_some() => {
+                vec![format!("{}_with_bodies", self.to_name())]
+            }
+            _ => vec![name],
+        }
+    }
+
+    /// Expected `--targets` of the datasets reading call targets
+    pub fn target_format(self) -> Option<&'static str> {
+        match self {
+            Datasets::Balances => Some("<token>:<holder> pairs"),
+            Datasets::Storage => Some("<contract>:<storage key> pairs"),
+            Datasets::Nonces => Some("account addresses"),
+            _ => None,
+        }
+    }
+}
+
+/// Position columns of events, decoded members with the same name get an `event_` prefix
+const EVENT_POSITION_COLUMNS: [&str; 8] = [
+    "block_number",
+    "block_hash",
+    "timestamp",
+    "transaction_index",
+    "event_index",
+    "transaction_event_index",
+    "tx_hash",
+    "contract_address",
+];
+
+/// Optional columns requested on the command line
+#[derive(Debug, Clone, Copy)]
+struct ColumnOptions {
+    flat_keys: usize,
+    flat_data: usize,
+}
+
+/// Transaction with its position in the chain
+struct BlockTx {
+    tx: Transaction,
+    block_number: u64,
+    block_hash: Felt,
+    timestamp: u64,
+    transaction_index: u64,
+}
+
+/// Call made by an account `__execute__`, indexed within its transaction
+struct BlockCall {
+    block_number: u64,
+    block_hash: Felt,
+    timestamp: u64,
+    transaction_index: u64,
+    transaction_hash: Felt,
+    caller: Felt,
+    call_index: u64,
+    // calldata layout of the account, cairo0 or cairo1
+    layout: &'static str,
+    contract_address: Felt,
+    selector: Felt,
+    // only known when the ABI defines the function
+    function_name: Option<String>,
+    calldata: Vec<Felt>,
+    arguments: Option<Vec<String>>,
+}
+
+/// ERC-20 `Transfer` event, with its `u256` amount as a decimal string
+struct Erc20Transfer {
+    log: BlockEvent,
+    from: Felt,
+    to: Felt,
+    amount: String,
+}
+
+/// ERC-721 `Transfer` or ERC-1155 `TransferSingle` / `TransferBatch` event, batches give one
+/// row per token id
+struct NftTransfer {
+    log: BlockEvent,
+    // only set by ERC-1155
+    operator: Option<Felt>,
+    from: Felt,
+    to: Felt,
+    token_id: String,
+    // only set by ERC-1155
+    amount: Option<String>,
+    // position of the token id in a batch, 0 for single transfers
+    batch_index: u64,
+}
+
+/// Token balance of a holder at a block, `None` when the balance call failed
+struct Balance {
+    block_number: u64,
+    timestamp: u64,
+    token: Felt,
+    holder: Felt,
+    balance: Option<String>,
 }
+
+/// Storage slot of a contract at a block, `None` when the contract is not deployed
+struct StorageValue {
+    block_number: u64,
+    timestamp: u64,
+    contract: Felt,
+    key: Felt,
+    value: Option<Felt>,
+}
+
+/// Nonce of an account at a block, `None` when the account is not deployed
+struct AccountNonce {
+    block_number: u64,
+    timestamp: u64,
+    account: Felt,
+    nonce: Option<Felt>,
+}
+
+/// Contract deployment, the transaction, deployer and deployment type are unknown when the node
+/// does not serve traces
+struct Deployment {
+    block_number: u64,
+    block_hash: Felt,
+    timestamp: u64,
+    transaction_hash: Option<Felt>,
+    // deploying contract of `deploy_syscall`
+    deployer: Option<Felt>,
+    contract_address: Felt,
+    class_hash: Felt,
+    // deploy_syscall, deploy_account or deploy
+    deployment_type: Option<&'static str>,
+}
+
+/// Class declared in a block, the transaction is unknown for classes declared without one
+/// (e.g. at regenesis)
+struct DeclaredClass {
+    block_number: u64,
+    block_hash: Felt,
+    timestamp: u64,
+    transaction_hash: Option<Felt>,
+    sender: Option<Felt>,
+    class_hash: Felt,
+    // only Cairo 1 classes are compiled
+    compiled_class_hash: Option<Felt>,
+    // cairo0 or cairo1
+    cairo_version: &'static str,
+    body: Option<ClassBody>,
+}
+
+/// Summary of a class definition
+struct ClassBody {
+    // JSON ABI
+    abi: String,
+    entry_point_selectors: Vec<Felt>,
+    // number of felts of the Sierra program, Cairo 1 only
+    sierra_program_size: Option<u64>,
+}
+
+/// L1 to L2 message consumed by an `L1Handler` transaction, or L2 to L1 message sent by a
+/// transaction
+struct Message {
+    block_number: u64,
+    block_hash: Felt,
+    timestamp: u64,
+    transaction_index: u64,
+    transaction_hash: Felt,
+    // position of the message in the transaction receipt
+    message_index: u64,
+    // l1_to_l2 or l2_to_l1
+    direction: &'static str,
+    // Ethereum sender of l1_to_l2 messages, or sending contract of l2_to_l1 ones
+    from_address: Felt,
+    // receiving contract of l1_to_l2 messages, or Ethereum recipient of l2_to_l1 ones
+    to_address: Felt,
+    // the following fields only exist for l1_to_l2 messages
+    selector: Option<Felt>,
+    payload: Vec<Felt>,
+    nonce: Option<u64>,
+    message_hash: Option<String>,
+}
+
+/// Aggregates of a block, senders are the accounts sending invoke and declare transactions
+struct BlockStats {
+    block_number: u64,
+    timestamp: u64,
+    tx_count: u64,
+    // transaction count by `TX_TYPE_COLUMNS` column
+    tx_type_counts: HashMap<&'static str, u64>,
+    event_count: u64,
+    unique_senders: u64,
+    total_fee_wei: Felt,
+    total_fee_fri: Felt,
+    total_steps: u64,
+    // gas paid to publish state diffs on L1, as calldata or blobs
+    total_l1_gas: u64,
+    total_l1_data_gas: u64,
+    reverted_tx_count: u64,
+}
+
+/// Transaction counts of `block_stats`, by type and version
+const TX_TYPE_COLUMNS: [&str; 11] = [
+    "invoke_v0_count",
+    "invoke_v1_count",
+    "invoke_v3_count",
+    "declare_v0_count",
+    "declare_v1_count",
+    "declare_v2_count",
+    "declare_v3_count",
+    "deploy_count",
+    "deploy_account_v1_count",
+    "deploy_account_v3_count",
+    "l1_handler_count",
+];
+
+/// `TX_TYPE_COLUMNS` column counting a transaction
+fn tx_type_column(tx: &Transaction) -> &'static str {
+    match tx {
+        Transaction::Invoke(InvokeTransaction::V0(_)) => TX_TYPE_COLUMNS[0],
+        Transaction::Invoke(InvokeTransaction::V1(_)) => TX_TYPE_COLUMNS[1],
+        Transaction::Invoke(InvokeTransaction::V3(_)) => TX_TYPE_COLUMNS[2],
+        Transaction::Declare(DeclareTransaction::V0(_)) => TX_TYPE_COLUMNS[3],
+        Transaction::Declare(DeclareTransaction::V1(_)) => TX_TYPE_COLUMNS[4],
+        Transaction::Declare(DeclareTransaction::V2(_)) => TX_TYPE_COLUMNS[5],
+        Transaction::Declare(DeclareTransaction::V3(_)) => TX_TYPE_COLUMNS[6],
+        Transaction::Deploy(_) => TX_TYPE_COLUMNS[7],
+        Transaction::DeployAccount(DeployAccountTransaction::V1(_)) => TX_TYPE_COLUMNS[8],
+        Transaction::DeployAccount(DeployAccountTransaction::V3(_)) => TX_TYPE_COLUMNS[9],
+        Transaction::L1Handler(_) => TX_TYPE_COLUMNS[10],
+    }
+}
+
+/// Datasets read from the pending block by `--pending`
+const PENDING_DATASETS: [Datasets; 3] = [Datasets::Blocks, Datasets::Transactions, Datasets::Logs];
+
+/// Event with its position in the chain, indexes count from the start of the block
+/// and of the transaction
+#[derive(Clone)]
+struct BlockEvent {
+    event: EmittedEvent,
+    timestamp: u64,
+    // none when the transaction is missing from the block, which the node should not answer
+    transaction_index: Option<u64>,
+    event_index: u64,
+    transaction_event_index: u64,
+}
+
 enum Data {
-    Blocks(Vec<BlockWithTxHashes>),
-    Transactions(Vec<(Transaction, u64)>),
-    Logs(Vec<EmittedEvent>),
-    None,
+    // with the L2 gas price, on nodes serving the RPC spec 0.8 or later
+    Blocks(Vec<(BlockWithTxHashes, Option<ResourcePrice>)>),
+    Transactions(Vec<BlockTx>),
+    Logs(Vec<BlockEvent>),
+    // one event type, with the ABI columns of its decoded values
+    DecodedLogs {
+        columns: Vec<(String, ValueKind)>,
+        logs: Vec<(BlockEvent, Vec<AbiValue>)>,
+    },
+    Calls(Vec<BlockCall>),
+    Erc20Transfers(Vec<Erc20Transfer>),
+    Erc721Transfers(Vec<NftTransfer>),
+    Erc1155Transfers(Vec<NftTransfer>),
+    Balances(Vec<Balance>),
+    Storage(Vec<StorageValue>),
+    Nonces(Vec<AccountNonce>),
+    Contracts(Vec<Deployment>),
+    Messages(Vec<Message>),
+    BlockStats(Vec<BlockStats>),
+    Classes {
+        classes: Vec<DeclaredClass>,
+        // whether the class definition columns are exported
+        with_bodies: bool,
+    },
 }
 
 impl Data {
-    pub fn to_dataframe(self) -> DataFrame {
+    /// Splits rows by partition directory, e.g. `date=2024-03-01`
+    pub fn partition(self, partition: Partition) -> BTreeMap<String, Data> {
+        match self {
+            Data::Blocks(blocks) => group_by(blocks, |(block, _)| {
+                partition.key(block.block_number, block.timestamp)
+            })
+            .into_iter()
+            .map(|(key, blocks)| (key, Data::Blocks(blocks)))
+            .collect(),
+            Data::Transactions(txs) => {
+                { group_by(txs, |tx| partition.key(tx.block_number, tx.timestamp)) }
+                    .into_iter()
+                    .map(|(key, txs)| (key, Data::Transactions(txs)))
+                    .collect()
+            }
+            Data::Logs(logs) => group_by(logs, |log| {
+                partition.key(log.event.block_number.unwrap_or(0), log.timestamp)
+            })
+            .into_iter()
+            .map(|(key, logs)| (key, Data::Logs(logs)))
+            .collect(),
+            Data::DecodedLogs { columns, logs } => group_by(logs, |(log, _)| {
+                partition.key(log.event.block_number.unwrap_or(0), log.timestamp)
+            })
+            .into_iter()
+            .map(|(key, logs)| {
+                let columns = columns.clone();
+                (key, Data::DecodedLogs { columns, logs })
+            })
+            .collect(),
+            Data::Balances(balances) => group_by(balances, |balance| {
+                partition.key(balance.block_number, balance.timestamp)
+            })
+            .into_iter()
+            .map(|(key, balances)| (key, Data::Balances(balances)))
+            .collect(),
+            Data::Storage(values) => group_by(values, |value| {
+                partition.key(value.block_number, value.timestamp)
+            })
+            .into_iter()
+            .map(|(key, values)| (key, Data::Storage(values)))
+            .collect(),
+            Data::Nonces(nonces) => group_by(nonces, |nonce| {
+                partition.key(nonce.block_number, nonce.timestamp)
+            })
+            .into_iter()
+            .map(|(key, nonces)| (key, Data::Nonces(nonces)))
+            .collect(),
+            Data::Contracts(deployments) => group_by(deployments, |deployment| {
+                partition.key(deployment.block_number, deployment.timestamp)
+            })
+            .into_iter()
+            .map(|(key, deployments)| (key, Data::Contracts(deployments)))
+            .collect(),
+            Data::Classes {
+                classes,
+                with_bodies,
+            } => group_by(classes, |class| {
+                partition.key(class.block_number, class.timestamp)
+            })
+            .into_iter()
+            .map(|(key, classes)| {
+                (
+                    key,
+                    Data::Classes {
+                        classes,
+                        with_bodies,
+                    },
+                )
+            })
+            .collect(),
+            Data::Messages(messages) => group_by(messages, |message| {
+                partition.key(message.block_number, message.timestamp)
+            })
+            .into_iter()
+            .map(|(key, messages)| (key, Data::Messages(messages)))
+            .collect(),
+            Data::BlockStats(stats) => group_by(stats, |stats| {
+                partition.key(stats.block_number, stats.timestamp)
+            })
+            .into_iter()
+            .map(|(key, stats)| (key, Data::BlockStats(stats)))
+            .collect(),
+            Data::Calls(calls) => group_by(calls, |call| {
+                partition.key(call.block_number, call.timestamp)
+            })
+            .into_iter()
+            .map(|(key, calls)| (key, Data::Calls(calls)))
+            .collect(),
+            Data::Erc20Transfers(transfers) => group_by(transfers, |transfer| {
+                partition.key(
+                    transfer.log.event.block_number.unwrap_or(0),
+                    transfer.log.timestamp,
+                )
+            })
+            .into_iter()
+            .map(|(key, transfers)| (key, Data::Erc20Transfers(transfers)))
+            .collect(),
+            Data::Erc721Transfers(transfers) => group_by(transfers, |transfer| {
+                partition.key(
+                    transfer.log.event.block_number.unwrap_or(0),
+                    transfer.log.timestamp,
+                )
+            })
+            .into_iter()
+            .map(|(key, transfers)| (key, Data::Erc721Transfers(transfers)))
+            .collect(),
+            Data::Erc1155Transfers(transfers) => group_by(transfers, |transfer| {
+                partition.key(
+                    transfer.log.event.block_number.unwrap_or(0),
+                    transfer.log.timestamp,
+                )
+            })
+            .into_iter()
+            .map(|(key, transfers)| (key, Data::Erc1155Transfers(transfers)))
+            .collect(),
+        }
+    }
+
+    pub fn into_dataframe(self, options: ColumnOptions) -> DataFrame {
+        // flattened event keys and data, e.g. key0 holds the event selector
+        let key_names: Vec<String> = (0..options.flat_keys)
+            .map(|i| format!("key{}", i))
+            .collect();
+        let data_names: Vec<String> = (0..options.flat_data)
+            .map(|i| format!("data{}", i))
+            .collect();
+
         let mut columns = BTreeMap::new();
+        let mut decoded_series = Vec::new();
+        let mut nullable_columns: BTreeMap<&str, Vec<Option<String>>> = BTreeMap::new();
+        let mut integer_columns: BTreeMap<&str, Vec<Option<u64>>> = BTreeMap::new();
+        let mut list_columns: BTreeMap<&str, Vec<Option<Series>>> = BTreeMap::new();
 
         match self {
             Data::Blocks(blocks) => {
-                for block in blocks {
+                for (block, l2_gas_price) in blocks {
                     columns
                         .entry("status")
                         .or_insert(vec![])
@@ -127,6 +720,30 @@ impl Data {
                         .entry("l1_gas_price_in_wei")
                         .or_insert(vec![])
                         .push(block.l1_gas_price.price_in_wei.to_string());
+                    columns
+                        .entry("l1_data_gas_price_in_fri")
+                        .or_insert(vec![])
+                        .push(block.l1_data_gas_price.price_in_fri.to_string());
+                    columns
+                        .entry("l1_data_gas_price_in_wei")
+                        .or_insert(vec![])
+                        .push(block.l1_data_gas_price.price_in_wei.to_string());
+                    nullable_columns
+                        .entry("l2_gas_price_in_fri")
+                        .or_insert(vec![])
+                        .push(
+                            l2_gas_price
+                                .as_ref()
+                                .map(|price| price.price_in_fri.to_string()),
+                        );
+                    nullable_columns
+                        .entry("l2_gas_price_in_wei")
+                        .or_insert(vec![])
+                        .push(l2_gas_price.map(|price| price.price_in_wei.to_string()));
+                    columns
+                        .entry("l1_da_mode")
+                        .or_insert(vec![])
+                        .push(format!("{:?}", block.l1_da_mode));
                     columns
                         .entry("starknet_version")
                         .or_insert(vec![])
@@ -138,11 +755,31 @@ impl Data {
                 }
             }
             Data::Transactions(txs) => {
-                for (tx, block_number) in txs {
+                for BlockTx {
+                    tx,
+                    block_number,
+                    block_hash,
+                    timestamp,
+                    transaction_index,
+                } in txs
+                {
+                    let details = TxDetails::from_transaction(&tx);
                     columns
                         .entry("block_number")
                         .or_insert(vec![])
                         .push(block_number.to_string());
+                    columns
+                        .entry("block_hash")
+                        .or_insert(vec![])
+                        .push(format!("0x{:x}", block_hash));
+                    columns
+                        .entry("timestamp")
+                        .or_insert(vec![])
+                        .push(timestamp.to_string());
+                    columns
+                        .entry("transaction_index")
+                        .or_insert(vec![])
+                        .push(transaction_index.to_string());
                     columns
                         .entry("transaction_hash")
                         .or_insert(vec![])
@@ -207,13 +844,15 @@ impl Data {
                             "DeployAccount".to_string(),
                             "V1".to_string(),
                             sub_tx.nonce.to_string(),
-                            "None".to_string(),
+                            // the deployed account sends its own deployment
+                            format!("0x{:x}", details.contract_address.unwrap()),
                         ),
                         Transaction::DeployAccount(DeployAccountTransaction::V3(sub_tx)) => (
                             "DeployAccount".to_string(),
                             "V3".to_string(),
                             sub_tx.nonce.to_string(),
-                            "None".to_string(),
+                            // the deployed account sends its own deployment
+                            format!("0x{:x}", details.contract_address.unwrap()),
                         ),
                     };
 
@@ -231,65 +870,890 @@ impl Data {
                         .entry("caller")
                         .or_insert(vec![])
                         .push(caller.to_string());
+
+                    list_columns
+                        .entry("calldata")
+                        .or_insert(vec![])
+                        .push(details.calldata.map(felts_to_series));
+                    list_columns
+                        .entry("signature")
+                        .or_insert(vec![])
+                        .push(details.signature.map(felts_to_series));
+                    nullable_columns
+                        .entry("max_fee")
+                        .or_insert(vec![])
+                        .push(details.max_fee.map(|fee| fee.to_string()));
+                    nullable_columns
+                        .entry("contract_address")
+                        .or_insert(vec![])
+                        .push(
+                            details
+                                .contract_address
+                                .map(|address| format!("0x{:x}", address)),
+                        );
+                    nullable_columns
+                        .entry("class_hash")
+                        .or_insert(vec![])
+                        .push(details.class_hash.map(|hash| format!("0x{:x}", hash)));
+                    nullable_columns
+                        .entry("contract_address_salt")
+                        .or_insert(vec![])
+                        .push(
+                            details
+                                .contract_address_salt
+                                .map(|salt| format!("0x{:x}", salt)),
+                        );
+                    list_columns
+                        .entry("constructor_calldata")
+                        .or_insert(vec![])
+                        .push(details.constructor_calldata.map(felts_to_series));
+                    let resource_bounds = details.resource_bounds;
+                    integer_columns
+                        .entry("l1_gas_max_amount")
+                        .or_insert(vec![])
+                        .push(
+                            resource_bounds
+                                .as_ref()
+                                .map(|bounds| bounds.l1_gas.max_amount),
+                        );
+                    // u128 prices, written as decimal strings
+                    nullable_columns
+                        .entry("l1_gas_max_price_per_unit")
+                        .or_insert(vec![])
+                        .push(
+                            resource_bounds
+                                .as_ref()
+                                .map(|bounds| bounds.l1_gas.max_price_per_unit.to_string()),
+                        );
+                    integer_columns
+                        .entry("l2_gas_max_amount")
+                        .or_insert(vec![])
+                        .push(
+                            resource_bounds
+                                .as_ref()
+                                .map(|bounds| bounds.l2_gas.max_amount),
+                        );
+                    nullable_columns
+                        .entry("l2_gas_max_price_per_unit")
+                        .or_insert(vec![])
+                        .push(
+                            resource_bounds
+                                .as_ref()
+                                .map(|bounds| bounds.l2_gas.max_price_per_unit.to_string()),
+                        );
+                    integer_columns
+                        .entry("tip")
+                        .or_insert(vec![])
+                        .push(details.tip);
+                    list_columns
+                        .entry("paymaster_data")
+                        .or_insert(vec![])
+                        .push(details.paymaster_data.map(felts_to_series));
+                    list_columns
+                        .entry("account_deployment_data")
+                        .or_insert(vec![])
+                        .push(details.account_deployment_data.map(felts_to_series));
+                    nullable_columns
+                        .entry("nonce_data_availability_mode")
+                        .or_insert(vec![])
+                        .push(
+                            details
+                                .nonce_data_availability_mode
+                                .map(|mode| format!("{:?}", mode)),
+                        );
+                    nullable_columns
+                        .entry("fee_data_availability_mode")
+                        .or_insert(vec![])
+                        .push(
+                            details
+                                .fee_data_availability_mode
+                                .map(|mode| format!("{:?}", mode)),
+                        );
+                    nullable_columns
+                        .entry("entry_point_selector")
+                        .or_insert(vec![])
+                        .push(
+                            details
+                                .entry_point_selector
+                                .map(|selector| format!("0x{:x}", selector)),
+                        );
                 }
             }
             Data::Logs(logs) => {
-                for event in logs {
+                for log in logs {
+                    push_event_position(&log, &mut columns, &mut nullable_columns);
+                    let event = log.event;
+                    columns
+                        .entry("contract_address")
+                        .or_insert(vec![])
+                        .push(format!("0x{:x}", event.from_address));
+                    for (i, name) in key_names.iter().enumerate() {
+                        nullable_columns
+                            .entry(name.as_str())
+                            .or_insert(vec![])
+                            .push(event.keys.get(i).map(|key| format!("0x{:x}", key)));
+                    }
+                    for (i, name) in data_names.iter().enumerate() {
+                        nullable_columns
+                            .entry(name.as_str())
+                            .or_insert(vec![])
+                            .push(event.data.get(i).map(|data| format!("0x{:x}", data)));
+                    }
+                    list_columns
+                        .entry("keys")
+                        .or_insert(vec![])
+                        .push(Some(felts_to_series(event.keys)));
+                    list_columns
+                        .entry("data")
+                        .or_insert(vec![])
+                        .push(Some(felts_to_series(event.data)));
+                }
+            }
+            Data::DecodedLogs {
+                columns: decoded_columns,
+                logs,
+            } => {
+                let mut decoded_values: Vec<Vec<AbiValue>> =
+                    decoded_columns.iter().map(|_| Vec::new()).collect();
+                for (log, values) in logs {
+                    push_event_position(&log, &mut columns, &mut nullable_columns);
+                    columns
+                        .entry("contract_address")
+                        .or_insert(vec![])
+                        .push(format!("0x{:x}", log.event.from_address));
+                    for (column, value) in decoded_values.iter_mut().zip(values) {
+                        column.push(value);
+                    }
+                }
+                for ((name, kind), values) in decoded_columns.into_iter().zip(decoded_values) {
+                    let name = if EVENT_POSITION_COLUMNS.contains(&name.as_str()) {
+                        format!("event_{}", name)
+                    } else {
+                        name
+                    };
+                    decoded_series.push(abi_values_to_series(name.as_str(), kind, values));
+                }
+            }
+            Data::Erc20Transfers(transfers) => {
+                for transfer in transfers {
+                    push_event_position(&transfer.log, &mut columns, &mut nullable_columns);
+                    columns
+                        .entry("token_address")
+                        .or_insert(vec![])
+                        .push(format!("0x{:x}", transfer.log.event.from_address));
+                    columns
+                        .entry("from_address")
+                        .or_insert(vec![])
+                        .push(format!("0x{:x}", transfer.from));
+                    columns
+                        .entry("to_address")
+                        .or_insert(vec![])
+                        .push(format!("0x{:x}", transfer.to));
+                    columns
+                        .entry("amount")
+                        .or_insert(vec![])
+                        .push(transfer.amount);
+                }
+            }
+            Data::Erc721Transfers(transfers) => {
+                for transfer in transfers {
+                    push_event_position(&transfer.log, &mut columns, &mut nullable_columns);
+                    columns
+                        .entry("token_address")
+                        .or_insert(vec![])
+                        .push(format!("0x{:x}", transfer.log.event.from_address));
+                    columns
+                        .entry("from_address")
+                        .or_insert(vec![])
+                        .push(format!("0x{:x}", transfer.from));
+                    columns
+                        .entry("to_address")
+                        .or_insert(vec![])
+                        .push(format!("0x{:x}", transfer.to));
+                    columns
+                        .entry("token_id")
+                        .or_insert(vec![])
+                        .push(transfer.token_id);
+                }
+            }
+            Data::Erc1155Transfers(transfers) => {
+                for transfer in transfers {
+                    push_event_position(&transfer.log, &mut columns, &mut nullable_columns);
+                    columns
+                        .entry("token_address")
+                        .or_insert(vec![])
+                        .push(format!("0x{:x}", transfer.log.event.from_address));
+                    nullable_columns.entry("operator").or_insert(vec![]).push(
+                        transfer
+                            .operator
+                            .map(|operator| format!("0x{:x}", operator)),
+                    );
+                    columns
+                        .entry("from_address")
+                        .or_insert(vec![])
+                        .push(format!("0x{:x}", transfer.from));
+                    columns
+                        .entry("to_address")
+                        .or_insert(vec![])
+                        .push(format!("0x{:x}", transfer.to));
+                    columns
+                        .entry("token_id")
+                        .or_insert(vec![])
+                        .push(transfer.token_id);
+                    nullable_columns
+                        .entry("amount")
+                        .or_insert(vec![])
+                        .push(transfer.amount);
+                    columns
+                        .entry("batch_index")
+                        .or_insert(vec![])
+                        .push(transfer.batch_index.to_string());
+                }
+            }
+            Data::Balances(balances) => {
+                for balance in balances {
                     columns
                         .entry("block_number")
                         .or_insert(vec![])
-                        .push(event.block_number.unwrap_or(0).to_string());
+                        .push(balance.block_number.to_string());
+                    columns
+                        .entry("timestamp")
+                        .or_insert(vec![])
+                        .push(balance.timestamp.to_string());
                     columns
-                        .entry("tx_hash")
+                        .entry("token_address")
                         .or_insert(vec![])
-                        .push(format!("0x{:x}", event.transaction_hash));
+                        .push(format!("0x{:x}", balance.token));
+                    columns
+                        .entry("holder_address")
+                        .or_insert(vec![])
+                        .push(format!("0x{:x}", balance.holder));
+                    nullable_columns
+                        .entry("balance")
+                        .or_insert(vec![])
+                        .push(balance.balance);
+                }
+            }
+            Data::Storage(values) => {
+                for value in values {
+                    columns
+                        .entry("block_number")
+                        .or_insert(vec![])
+                        .push(value.block_number.to_string());
+                    columns
+                        .entry("timestamp")
+                        .or_insert(vec![])
+                        .push(value.timestamp.to_string());
                     columns
                         .entry("contract_address")
                         .or_insert(vec![])
-                        .push(format!("0x{:x}", event.from_address));
-                    let mut keys = "[".to_string();
-                    let n = event.keys.len();
-                    for (i, key) in event.keys.iter().enumerate() {
-                        keys.push_str(format!("0x{:x}", key).as_str());
-                        if i < n - 1 {
-                            keys.push_str(",");
-                        }
+                        .push(format!("0x{:x}", value.contract));
+                    columns
+                        .entry("key")
+                        .or_insert(vec![])
+                        .push(format!("0x{:x}", value.key));
+                    nullable_columns
+                        .entry("value")
+                        .or_insert(vec![])
+                        .push(value.value.map(|value| format!("0x{:x}", value)));
+                }
+            }
+            Data::Nonces(nonces) => {
+                for nonce in nonces {
+                    columns
+                        .entry("block_number")
+                        .or_insert(vec![])
+                        .push(nonce.block_number.to_string());
+                    columns
+                        .entry("timestamp")
+                        .or_insert(vec![])
+                        .push(nonce.timestamp.to_string());
+                    columns
+                        .entry("account_address")
+                        .or_insert(vec![])
+                        .push(format!("0x{:x}", nonce.account));
+                    integer_columns
+                        .entry("nonce")
+                        .or_insert(vec![])
+                        .push(nonce.nonce.and_then(|nonce| u64::try_from(nonce).ok()));
+                }
+            }
+            Data::Contracts(deployments) => {
+                for deployment in deployments {
+                    columns
+                        .entry("block_number")
+                        .or_insert(vec![])
+                        .push(deployment.block_number.to_string());
+                    columns
+                        .entry("block_hash")
+                        .or_insert(vec![])
+                        .push(format!("0x{:x}", deployment.block_hash));
+                    columns
+                        .entry("timestamp")
+                        .or_insert(vec![])
+                        .push(deployment.timestamp.to_string());
+                    nullable_columns
+                        .entry("transaction_hash")
+                        .or_insert(vec![])
+                        .push(
+                            deployment
+                                .transaction_hash
+                                .map(|hash| format!("0x{:x}", hash)),
+                        );
+                    nullable_columns.entry("deployer").or_insert(vec![]).push(
+                        deployment
+                            .deployer
+                            .map(|deployer| format!("0x{:x}", deployer)),
+                    );
+                    columns
+                        .entry("contract_address")
+                        .or_insert(vec![])
+                        .push(format!("0x{:x}", deployment.contract_address));
+                    columns
+                        .entry("class_hash")
+                        .or_insert(vec![])
+                        .push(format!("0x{:x}", deployment.class_hash));
+                    nullable_columns
+                        .entry("deployment_type")
+                        .or_insert(vec![])
+                        .push(deployment.deployment_type.map(str::to_string));
+                }
+            }
+            Data::Classes {
+                classes,
+                with_bodies,
+            } => {
+                for class in classes {
+                    columns
+                        .entry("block_number")
+                        .or_insert(vec![])
+                        .push(class.block_number.to_string());
+                    columns
+                        .entry("block_hash")
+                        .or_insert(vec![])
+                        .push(format!("0x{:x}", class.block_hash));
+                    columns
+                        .entry("timestamp")
+                        .or_insert(vec![])
+                        .push(class.timestamp.to_string());
+                    nullable_columns
+                        .entry("transaction_hash")
+                        .or_insert(vec![])
+                        .push(class.transaction_hash.map(|hash| format!("0x{:x}", hash)));
+                    nullable_columns
+                        .entry("sender_address")
+                        .or_insert(vec![])
+                        .push(class.sender.map(|sender| format!("0x{:x}", sender)));
+                    columns
+                        .entry("class_hash")
+                        .or_insert(vec![])
+                        .push(format!("0x{:x}", class.class_hash));
+                    nullable_columns
+                        .entry("compiled_class_hash")
+                        .or_insert(vec![])
+                        .push(
+                            class
+                                .compiled_class_hash
+                                .map(|hash| format!("0x{:x}", hash)),
+                        );
+                    columns
+                        .entry("cairo_version")
+                        .or_insert(vec![])
+                        .push(class.cairo_version.to_string());
+                    if !with_bodies {
+                        continue;
                     }
-                    keys.push_str("]");
-                    columns.entry("keys").or_insert(vec![]).push(keys);
-                    let m = event.data.len();
-                    let mut data = "[".to_string();
-                    for (i, d) in event.data.iter().enumerate() {
-                        data.push_str(format!("0x{:x}", d).as_str());
-                        if i < m - 1 {
-                            data.push_str(",");
-                        }
+                    let body = class.body;
+                    nullable_columns
+                        .entry("abi")
+                        .or_insert(vec![])
+                        .push(body.as_ref().map(|body| body.abi.clone()));
+                    integer_columns
+                        .entry("sierra_program_size")
+                        .or_insert(vec![])
+                        .push(body.as_ref().and_then(|body| body.sierra_program_size));
+                    list_columns
+                        .entry("entry_point_selectors")
+                        .or_insert(vec![])
+                        .push(body.map(|body| felts_to_series(body.entry_point_selectors)));
+                }
+            }
+            Data::Messages(messages) => {
+                for message in messages {
+                    columns
+                        .entry("block_number")
+                        .or_insert(vec![])
+                        .push(message.block_number.to_string());
+                    columns
+                        .entry("block_hash")
+                        .or_insert(vec![])
+                        .push(format!("0x{:x}", message.block_hash));
+                    columns
+                        .entry("timestamp")
+                        .or_insert(vec![])
+                        .push(message.timestamp.to_string());
+                    columns
+                        .entry("transaction_index")
+                        .or_insert(vec![])
+                        .push(message.transaction_index.to_string());
+                    columns
+                        .entry("transaction_hash")
+                        .or_insert(vec![])
+                        .push(format!("0x{:x}", message.transaction_hash));
+                    columns
+                        .entry("message_index")
+                        .or_insert(vec![])
+                        .push(message.message_index.to_string());
+                    columns
+                        .entry("direction")
+                        .or_insert(vec![])
+                        .push(message.direction.to_string());
+                    columns
+                        .entry("from_address")
+                        .or_insert(vec![])
+                        .push(format!("0x{:x}", message.from_address));
+                    columns
+                        .entry("to_address")
+                        .or_insert(vec![])
+                        .push(format!("0x{:x}", message.to_address));
+                    nullable_columns
+                        .entry("selector")
+                        .or_insert(vec![])
+                        .push(message.selector.map(|selector| format!("0x{:x}", selector)));
+                    integer_columns
+                        .entry("nonce")
+                        .or_insert(vec![])
+                        .push(message.nonce);
+                    nullable_columns
+                        .entry("message_hash")
+                        .or_insert(vec![])
+                        .push(message.message_hash);
+                    list_columns
+                        .entry("payload")
+                        .or_insert(vec![])
+                        .push(Some(felts_to_series(message.payload)));
+                }
+            }
+            Data::BlockStats(stats) => {
+                for stats in stats {
+                    columns
+                        .entry("block_number")
+                        .or_insert(vec![])
+                        .push(stats.block_number.to_string());
+                    columns
+                        .entry("timestamp")
+                        .or_insert(vec![])
+                        .push(stats.timestamp.to_string());
+                    integer_columns
+                        .entry("tx_count")
+                        .or_insert(vec![])
+                        .push(Some(stats.tx_count));
+                    for column in TX_TYPE_COLUMNS {
+                        integer_columns.entry(column).or_insert(vec![]).push(Some(
+                            stats
+                                .tx_type_counts
+                                .get(column)
+                                .copied()
+                                .unwrap_or_default(),
+                        ));
                     }
-                    data.push_str("]");
-                    columns.entry("data").or_insert(vec![]).push(data);
+                    integer_columns
+                        .entry("event_count")
+                        .or_insert(vec![])
+                        .push(Some(stats.event_count));
+                    integer_columns
+                        .entry("unique_senders")
+                        .or_insert(vec![])
+                        .push(Some(stats.unique_senders));
+                    columns
+                        .entry("total_fee_wei")
+                        .or_insert(vec![])
+                        .push(stats.total_fee_wei.to_string());
+                    columns
+                        .entry("total_fee_fri")
+                        .or_insert(vec![])
+                        .push(stats.total_fee_fri.to_string());
+                    integer_columns
+                        .entry("total_steps")
+                        .or_insert(vec![])
+                        .push(Some(stats.total_steps));
+                    integer_columns
+                        .entry("total_l1_gas")
+                        .or_insert(vec![])
+                        .push(Some(stats.total_l1_gas));
+                    integer_columns
+                        .entry("total_l1_data_gas")
+                        .or_insert(vec![])
+                        .push(Some(stats.total_l1_data_gas));
+                    integer_columns
+                        .entry("reverted_tx_count")
+                        .or_insert(vec![])
+                        .push(Some(stats.reverted_tx_count));
+                }
+            }
+            Data::Calls(calls) => {
+                for call in calls {
+                    columns
+                        .entry("block_number")
+                        .or_insert(vec![])
+                        .push(call.block_number.to_string());
+                    columns
+                        .entry("block_hash")
+                        .or_insert(vec![])
+                        .push(format!("0x{:x}", call.block_hash));
+                    columns
+                        .entry("timestamp")
+                        .or_insert(vec![])
+                        .push(call.timestamp.to_string());
+                    columns
+                        .entry("transaction_index")
+                        .or_insert(vec![])
+                        .push(call.transaction_index.to_string());
+                    columns
+                        .entry("transaction_hash")
+                        .or_insert(vec![])
+                        .push(format!("0x{:x}", call.transaction_hash));
+                    columns
+                        .entry("caller")
+                        .or_insert(vec![])
+                        .push(format!("0x{:x}", call.caller));
+                    columns
+                        .entry("call_index")
+                        .or_insert(vec![])
+                        .push(call.call_index.to_string());
+                    columns
+                        .entry("layout")
+                        .or_insert(vec![])
+                        .push(call.layout.to_string());
+                    columns
+                        .entry("contract_address")
+                        .or_insert(vec![])
+                        .push(format!("0x{:x}", call.contract_address));
+                    columns
+                        .entry("selector")
+                        .or_insert(vec![])
+                        .push(format!("0x{:x}", call.selector));
+                    nullable_columns
+                        .entry("function_name")
+                        .or_insert(vec![])
+                        .push(call.function_name);
+                    list_columns
+                        .entry("calldata")
+                        .or_insert(vec![])
+                        .push(Some(felts_to_series(call.calldata)));
+                    list_columns
+                        .entry("arguments")
+                        .or_insert(vec![])
+                        .push(call.arguments.map(|arguments| Series::new("", arguments)));
                 }
             }
-            Data::None => (),
         };
-        DataFrame::new(
-            columns
+        let mut series = columns
+            .into_iter()
+            .map(|(name, values)| Series::new(name, values))
+            .collect::<Vec<_>>();
+        series.extend(
+            nullable_columns
+                .into_iter()
+                .map(|(name, values)| Series::new(name, values)),
+        );
+        series.extend(
+            integer_columns
+                .into_iter()
+                .map(|(name, values)| Series::new(name, values)),
+        );
+        series.extend(
+            list_columns
                 .into_iter()
-                .map(|(name, values)| Series::new(name, values))
+                .map(|(name, values)| Series::new(name, values)),
+        );
+        series.extend(decoded_series);
+        series.sort_by(|a, b| a.name().cmp(b.name()));
+        DataFrame::new(series).unwrap()
+    }
+}
+
+/// Transaction fields which only exist for some transaction types
+#[derive(Default)]
+struct TxDetails {
+    calldata: Option<Vec<Felt>>,
+    signature: Option<Vec<Felt>>,
+    max_fee: Option<Felt>,
+    class_hash: Option<Felt>,
+    contract_address_salt: Option<Felt>,
+    constructor_calldata: Option<Vec<Felt>>,
+    entry_point_selector: Option<Felt>,
+    // called contract, or deployed contract for deployments
+    contract_address: Option<Felt>,
+    // V3 fee market, max prices are u128 and exported as decimal strings
+    resource_bounds: Option<ResourceBoundsMapping>,
+    tip: Option<u64>,
+    paymaster_data: Option<Vec<Felt>>,
+    account_deployment_data: Option<Vec<Felt>>,
+    nonce_data_availability_mode: Option<DataAvailabilityMode>,
+    fee_data_availability_mode: Option<DataAvailabilityMode>,
+}
+
+impl TxDetails {
+    fn from_transaction(tx: &Transaction) -> Self {
+        match tx {
+            Transaction::Invoke(InvokeTransaction::V0(sub_tx)) => TxDetails {
+                calldata: Some(sub_tx.calldata.clone()),
+                signature: Some(sub_tx.signature.clone()),
+                max_fee: Some(sub_tx.max_fee),
+                entry_point_selector: Some(sub_tx.entry_point_selector),
+                contract_address: Some(sub_tx.contract_address),
+                ..Default::default()
+            },
+            Transaction::Invoke(InvokeTransaction::V1(sub_tx)) => TxDetails {
+                calldata: Some(sub_tx.calldata.clone()),
+                signature: Some(sub_tx.signature.clone()),
+                max_fee: Some(sub_tx.max_fee),
+                ..Default::default()
+            },
+            Transaction::Invoke(InvokeTransaction::V3(sub_tx)) => TxDetails {
+                calldata: Some(sub_tx.calldata.clone()),
+                signature: Some(sub_tx.signature.clone()),
+                resource_bounds: Some(sub_tx.resource_bounds.clone()),
+                tip: Some(sub_tx.tip),
+                paymaster_data: Some(sub_tx.paymaster_data.clone()),
+                account_deployment_data: Some(sub_tx.account_deployment_data.clone()),
+                nonce_data_availability_mode: Some(sub_tx.nonce_data_availability_mode),
+                fee_data_availability_mode: Some(sub_tx.fee_data_availability_mode),
+                ..Default::default()
+            },
+            Transaction::L1Handler(sub_tx) => TxDetails {
+                calldata: Some(sub_tx.calldata.clone()),
+                entry_point_selector: Some(sub_tx.entry_point_selector),
+                contract_address: Some(sub_tx.contract_address),
+                ..Default::default()
+            },
+            Transaction::Declare(DeclareTransaction::V0(sub_tx)) => TxDetails {
+                signature: Some(sub_tx.signature.clone()),
+                max_fee: Some(sub_tx.max_fee),
+                class_hash: Some(sub_tx.class_hash),
+                ..Default::default()
+            },
+            Transaction::Declare(DeclareTransaction::V1(sub_tx)) => TxDetails {
+                signature: Some(sub_tx.signature.clone()),
+                max_fee: Some(sub_tx.max_fee),
+                class_hash: Some(sub_tx.class_hash),
+                ..Default::default()
+            },
+            Transaction::Declare(DeclareTransaction::V2(sub_tx)) => TxDetails {
+                signature: Some(sub_tx.signature.clone()),
+                max_fee: Some(sub_tx.max_fee),
+                class_hash: Some(sub_tx.class_hash),
+                ..Default::default()
+            },
+            Transaction::Declare(DeclareTransaction::V3(sub_tx)) => TxDetails {
+                signature: Some(sub_tx.signature.clone()),
+                class_hash: Some(sub_tx.class_hash),
+                resource_bounds: Some(sub_tx.resource_bounds.clone()),
+                tip: Some(sub_tx.tip),
+                paymaster_data: Some(sub_tx.paymaster_data.clone()),
+                account_deployment_data: Some(sub_tx.account_deployment_data.clone()),
+                nonce_data_availability_mode: Some(sub_tx.nonce_data_availability_mode),
+                fee_data_availability_mode: Some(sub_tx.fee_data_availability_mode),
+                ..Default::default()
+            },
+            Transaction::Deploy(sub_tx) => TxDetails {
+                class_hash: Some(sub_tx.class_hash),
+                contract_address_salt: Some(sub_tx.contract_address_salt),
+                constructor_calldata: Some(sub_tx.constructor_calldata.clone()),
+                contract_address: Some(deployed_address(
+                    sub_tx.contract_address_salt,
+                    sub_tx.class_hash,
+                    &sub_tx.constructor_calldata,
+                )),
+                ..Default::default()
+            },
+            Transaction::DeployAccount(DeployAccountTransaction::V1(sub_tx)) => TxDetails {
+                signature: Some(sub_tx.signature.clone()),
+                max_fee: Some(sub_tx.max_fee),
+                class_hash: Some(sub_tx.class_hash),
+                contract_address_salt: Some(sub_tx.contract_address_salt),
+                constructor_calldata: Some(sub_tx.constructor_calldata.clone()),
+                contract_address: Some(deployed_address(
+                    sub_tx.contract_address_salt,
+                    sub_tx.class_hash,
+                    &sub_tx.constructor_calldata,
+                )),
+                ..Default::default()
+            },
+            Transaction::DeployAccount(DeployAccountTransaction::V3(sub_tx)) => TxDetails {
+                signature: Some(sub_tx.signature.clone()),
+                class_hash: Some(sub_tx.class_hash),
+                contract_address_salt: Some(sub_tx.contract_address_salt),
+                constructor_calldata: Some(sub_tx.constructor_calldata.clone()),
+                contract_address: Some(deployed_address(
+                    sub_tx.contract_address_salt,
+                    sub_tx.class_hash,
+                    &sub_tx.constructor_calldata,
+                )),
+                resource_bounds: Some(sub_tx.resource_bounds.clone()),
+                tip: Some(sub_tx.tip),
+                paymaster_data: Some(sub_tx.paymaster_data.clone()),
+                nonce_data_availability_mode: Some(sub_tx.nonce_data_availability_mode),
+                fee_data_availability_mode: Some(sub_tx.fee_data_availability_mode),
+                ..Default::default()
+            },
+        }
+    }
+}
+
+/// Address of a contract deployed by `Deploy` or `DeployAccount`, which use a zero deployer
+fn deployed_address(salt: Felt, class_hash: Felt, constructor_calldata: &[Felt]) -> Felt {
+    get_contract_address(salt, class_hash, constructor_calldata, Felt::ZERO)
+}
+
+/// List cell holding felts as hex strings
+fn felts_to_series(felts: Vec<Felt>) -> Series {
+    Series::new(
+        "",
+        felts
+            .iter()
+            .map(|felt| format!("0x{:x}", felt))
+            .collect::<Vec<_>>(),
+    )
+}
+
+/// Block and position columns shared by every event dataset
+fn push_event_position<'a>(
+    log: &BlockEvent,
+    columns: &mut BTreeMap<&'a str, Vec<String>>,
+    nullable_columns: &mut BTreeMap<&'a str, Vec<Option<String>>>,
+) {
+    columns
+        .entry("block_number")
+        .or_insert(vec![])
+        .push(log.event.block_number.unwrap_or(0).to_string());
+    nullable_columns
+        .entry("block_hash")
+        .or_insert(vec![])
+        .push(log.event.block_hash.map(|hash| format!("0x{:x}", hash)));
+    columns
+        .entry("timestamp")
+        .or_insert(vec![])
+        .push(log.timestamp.to_string());
+    nullable_columns
+        .entry("transaction_index")
+        .or_insert(vec![])
+        .push(log.transaction_index.map(|index| index.to_string()));
+    columns
+        .entry("event_index")
+        .or_insert(vec![])
+        .push(log.event_index.to_string());
+    columns
+        .entry("transaction_event_index")
+        .or_insert(vec![])
+        .push(log.transaction_event_index.to_string());
+    columns
+        .entry("tx_hash")
+        .or_insert(vec![])
+        .push(format!("0x{:x}", log.event.transaction_hash));
+}
+
+/// Typed column of decoded ABI values, values of another kind are null
+fn abi_values_to_series(name: &str, kind: ValueKind, values: Vec<AbiValue>) -> Series {
+    match kind {
+        ValueKind::Text => Series::new(
+            name,
+            values
+                .iter()
+                .map(|value| value.to_text())
                 .collect::<Vec<_>>(),
-        )
-        .unwrap()
+        ),
+        ValueKind::Integer => Series::new(
+            name,
+            values
+                .iter()
+                .map(|value| match value {
+                    AbiValue::Integer(x) => Some(*x),
+                    _ => None,
+                })
+                .collect::<Vec<_>>(),
+        ),
+        ValueKind::Boolean => Series::new(
+            name,
+            values
+                .iter()
+                .map(|value| match value {
+                    AbiValue::Boolean(x) => Some(*x),
+                    _ => None,
+                })
+                .collect::<Vec<_>>(),
+        ),
+        ValueKind::List => Series::new(
+            name,
+            values
+                .into_iter()
+                .map(|value| match value {
+                    AbiValue::List(items) => Some(Series::new("", items)),
+                    _ => None,
+                })
+                .collect::<Vec<_>>(),
+        ),
     }
 }
+
+/// CSV cannot hold nested values, so list columns are written as `[a,b,c]` strings
+fn stringify_list_columns(dataframe: &mut DataFrame) {
+    let list_columns = dataframe
+        .get_columns()
+        .iter()
+        .filter(|column| matches!(column.dtype(), DataType::List(_)))
+        .map(|column| column.name().to_string())
+        .collect::<Vec<_>>();
+
+    for name in list_columns {
+        let values = dataframe
+            .column(name.as_str())
+            .unwrap()
+            .list()
+            .unwrap()
+            .into_iter()
+            .map(|value| {
+                value.map(|value| {
+                    let value = value.cast(&DataType::String).unwrap();
+                    let items = value.str().unwrap().into_iter().flatten();
+                    format!("[{}]", items.collect::<Vec<_>>().join(","))
+                })
+            })
+            .collect::<Vec<_>>();
+        dataframe
+            .replace(name.as_str(), Series::new(name.as_str(), values))
+            .unwrap();
+    }
+}
+/// Chunk of a block range and its sampling, as (start, end, step, offset)
+type SampledChunk = (u64, u64, u64, u64);
+
 #[tokio::main]
 async fn main() -> Result<(), SerpicoError> {
     let args = Cli::parse();
 
-    let dataset = match args.dataset.as_str() {
-        "blocks" | "block" => Datasets::Blocks,
-        "transactions" | "transaction" => Datasets::Transactions,
-        "logs" | "events" | "log" => Datasets::Logs,
-        _ => Datasets::None,
-    };
+    let datasets = parse_datasets(args.dataset)?;
+    let finality = parse_finality(args.finality)?;
+    if args.pending && finality == Finality::L1 {
+        return Err(SerpicoError::BlockParsingErr(
+            "--pending captures a block not even accepted on L2, it cannot be combined with --finality l1"
+                .to_string(),
+        ));
+    }
+    if args.pending {
+        let unsupported: Vec<&str> = datasets
+            .iter()
+            .filter(|dataset| !PENDING_DATASETS.contains(dataset))
+            .map(|dataset| dataset.to_name())
+            .collect();
+        if !unsupported.is_empty() {
+            return Err(SerpicoError::DatasetParsingErr(format!(
+                "{} cannot be captured from the pending block, only blocks, transactions and logs can",
+                unsupported.join(", ")
+            )));
+        }
+    }
 
     let stark_client = JsonRpcClient::new(HttpTransport::new(
         Url::parse(args.rpc_url.as_str()).map_err(SerpicoError::UrlParsingErr)?,
@@ -300,98 +1764,438 @@ async fn main() -> Result<(), SerpicoError> {
         .await
         .map_err(SerpicoError::ClientErr)?;
 
-    let (block_start, block_end) = parse_blocks(args.blocks, block_number)?;
-
-    let mut chunks_seen: Vec<(u64, u64)> = Vec::new();
-
-    if let Ok(entries) = read_dir(args.path.clone()) {
-        for entry in entries {
-            if let Ok(entry) = entry {
-                let file_name = entry.file_name();
-                let chunks = file_name
-                    .to_str()
-                    .unwrap()
-                    .split(|c| c == '_' || c == '.')
-                    .collect::<Vec<&str>>();
-
-                if chunks.len() == 6
-                    && chunks[0] == dataset.to_name()
-                    && chunks[1] == "from"
-                    && chunks[3] == "to"
-                    && chunks[5] == args.export_type
-                {
-                    if let Ok(block_start) = chunks[2].parse::<u64>() {
-                        if let Ok(block_end) = chunks[4].parse::<u64>() {
-                            chunks_seen.push((block_start, block_end));
-                        }
-                    }
+    let chain_id = stark_client
+        .chain_id()
+        .await
+        .map_err(SerpicoError::ClientErr)?;
+
+    // blocks accepted on L1, to stop at them or to track the chunks exported before them
+    let last_block_on_l1 = if args.pending {
+        None
+    } else {
+        last_block_on_l1(&stark_client, block_number).await?
+    };
+    let last_block = match finality {
+        Finality::L2 => block_number,
+        Finality::L1 => {
+            let last_block = last_block_on_l1.ok_or_else(|| {
+                SerpicoError::BlockParsingErr("no block is accepted on L1 yet".to_string())
+            })?;
+            println!(
+                "Stopping at block {}, the latest accepted on L1",
+                last_block
+            );
+            last_block
+        }
+    };
+
+    let block_ranges = if let Some(blocks) = args.blocks {
+        parse_blocks(blocks, last_block)?
+    } else if args.pending {
+        Vec::new()
+    } else {
+        let timestamp_ranges = match (args.timestamps, args.dates) {
+            (Some(timestamps), _) => parse_timestamps(timestamps)?,
+            (None, Some(dates)) => parse_dates(dates)?,
+            (None, None) => unreachable!("clap requires one block interval option"),
+        };
+        // the cache lives in the output directory, which may not exist yet
+        create_dir_all(args.path.as_str()).map_err(SerpicoError::IoErr)?;
+        let cache_file = format!("{}/.serpico_timestamps_0x{:x}.csv", args.path, chain_id);
+        resolve_timestamps(&stark_client, timestamp_ranges, last_block, cache_file).await?
+    };
+
+    let partition = parse_partition(args.partition)?;
+    let column_options = ColumnOptions {
+        flat_keys: args.flat_keys,
+        flat_data: args.flat_data,
+    };
+    let network = network_name(chain_id);
+
+    let contract = args.contract.map(parse_address).transpose()?;
+    // calls only use an ABI to name functions, when one is given
+    let with_abi = datasets.contains(&Datasets::DecodedLogs)
+        || (datasets.contains(&Datasets::Calls) && (args.abi.is_some() || contract.is_some()));
+    let abi_decoder = if with_abi {
+        let abi = load_abi(&stark_client, args.abi.as_deref(), contract).await?;
+        let abi_decoder = AbiDecoder::new(&abi)?;
+        if datasets.contains(&Datasets::DecodedLogs) && abi_decoder.events().is_empty() {
+            return Err(SerpicoError::AbiErr(
+                "the ABI does not define any event to decode".to_string(),
+            ));
+        }
+        Some(abi_decoder)
+    } else {
+        None
+    };
+    // balances, storage and nonces read the same list of call targets
+    let targets = parse_targets(args.targets, args.targets_file)?;
+    let target_datasets: Vec<Datasets> = datasets
+        .iter()
+        .copied()
+        .filter(|dataset| dataset.target_format().is_some())
+        .collect();
+    if target_datasets.len() > 1 {
+        return Err(SerpicoError::DatasetParsingErr(format!(
+            "{} cannot share --targets, fetch them separately",
+            target_datasets
+                .iter()
+                .map(|dataset| dataset.to_name())
+                .collect::<Vec<_>>()
+                .join(", ")
+        )));
+    }
+    let mut params = FetchParams {
+        contract,
+        abi_decoder,
+        balance_targets: Vec::new(),
+        storage_targets: Vec::new(),
+        nonce_targets: Vec::new(),
+        class_bodies_dir: None,
+        nft_tokens: Mutex::new(HashMap::new()),
+        l2_gas_transport: None,
+    };
+    if args.class_bodies && datasets.contains(&Datasets::Classes) {
+        let class_bodies_dir = format!("{}/class_bodies", args.path);
+        create_dir_all(class_bodies_dir.as_str()).map_err(SerpicoError::IoErr)?;
+        params.class_bodies_dir = Some(class_bodies_dir);
+    }
+    if datasets.contains(&Datasets::Blocks)
+        && rpc::serves_l2_gas_price(&stark_client)
+            .await
+            .map_err(SerpicoError::ClientErr)?
+    {
+        params.l2_gas_transport = Some(HttpTransport::new(
+            Url::parse(args.rpc_url.as_str()).map_err(SerpicoError::UrlParsingErr)?,
+        ));
+    }
+    if let Some(dataset) = target_datasets.first() {
+        if targets.is_empty() {
+            return Err(SerpicoError::DatasetParsingErr(format!(
+                "{} needs {} from --targets or --targets-file",
+                dataset.to_name(),
+                dataset.target_format().unwrap_or_default()
+            )));
+        }
+        match dataset {
+            Datasets::Balances => {
+                params.balance_targets = targets
+                    .iter()
+                    .map(|target| parse_address_pair(target))
+                    .collect::<Result<Vec<_>, _>>()?
+            }
+            Datasets::Storage => {
+                params.storage_targets = targets
+                    .iter()
+                    .map(|target| parse_storage_target(target))
+                    .collect::<Result<Vec<_>, _>>()?
+            }
+            Datasets::Nonces => {
+                params.nonce_targets = targets
+                    .into_iter()
+                    .map(parse_address)
+                    .collect::<Result<Vec<_>, _>>()?
+            }
+            _ => unreachable!("only target datasets read --targets"),
+        }
+    }
+
+    if args.pending {
+        return write_pending_snapshot(
+            &stark_client,
+            &datasets,
+            &params,
+            args.path.as_str(),
+            args.export_type.as_str(),
+            column_options,
+        )
+        .await;
+    }
+
+    let mut l2_chunks = L2Chunks::load(format!(
+        "{}/.serpico_l2_chunks_0x{:x}.csv",
+        args.path, chain_id
+    ))?;
+    l2_chunks
+        .detect_reorgs(&stark_client, block_number, last_block_on_l1)
+        .await?;
+
+    // chunks to fetch, with the datasets still missing for each of them
+    let mut block_chunks: BTreeMap<SampledChunk, (BlockRange, Vec<Datasets>)> = BTreeMap::new();
+    let mut file_templates = HashMap::new();
+    let samplings: BTreeSet<(u64, u64)> = block_ranges
+        .iter()
+        .map(|range| (range.step, range.offset()))
+        .collect();
+    for dataset in datasets {
+        for (step, offset) in samplings.iter().copied() {
+            // a chunk is only done once every table of the dataset holds it
+            let mut chunks_seen: Option<Vec<(u64, u64)>> = None;
+            // recorded chunks fetched again with their own bounds, so that their files are replaced
+            let mut refetched: BTreeSet<(u64, u64)> = BTreeSet::new();
+            for table in dataset.tables(&params) {
+                let table = sampled_table(table.as_str(), step, offset);
+                let file_template = FileTemplate::new(
+                    args.file_template.as_str(),
+                    table.as_str(),
+                    network.as_str(),
+                    args.export_type.as_str(),
+                    args.label.as_str(),
+                )?;
+                let table_chunks = scan_chunks(
+                    args.path.as_str(),
+                    table.as_str(),
+                    &file_template,
+                    partition,
+                );
+                if args.refresh_finality {
+                    // chunks now accepted on L1 are exported again to update their status
+                    refetched.extend(l2_chunks.proven(table.as_str(), last_block_on_l1));
+                }
+                refetched.extend(l2_chunks.reorged(table.as_str()));
+                chunks_seen = Some(match chunks_seen {
+                    Some(chunks) => chunks
+                        .into_iter()
+                        .filter(|chunk| table_chunks.contains(chunk))
+                        .collect(),
+                    None => table_chunks,
+                });
+                file_templates.insert(table, file_template);
+            }
+
+            let mut chunks_seen = chunks_seen.unwrap_or_default();
+            chunks_seen.extend(refetched.iter().copied());
+            chunks_seen.sort();
+            chunks_seen.dedup();
+            for block_range in block_ranges
+                .iter()
+                .filter(|range| (range.step, range.offset()) == (step, offset))
+            {
+                let range_chunks = split_block_chunks(
+                    block_range.start,
+                    block_range.end,
+                    args.chunk_size,
+                    &mut chunks_seen,
+                );
+                let range_refetched = refetched
+                    .iter()
+                    .copied()
+                    .filter(|(start, end)| block_range.start <= *start && *end <= block_range.end);
+                for (chunk_start, chunk_end) in range_chunks.into_iter().chain(range_refetched) {
+                    block_chunks
+                        .entry((chunk_start, chunk_end, step, offset))
+                        .or_insert((*block_range, Vec::new()))
+                        .1
+                        .push(dataset);
                 }
             }
         }
     }
-    let block_chunks =
-        split_block_chunks(block_start, block_end, args.chunk_size, &mut chunks_seen);
 
     println!("There are {} chunks", block_chunks.len());
 
-    // TODO analyze output directory to prevent redundant data downloading
-
     let rpc_url = Arc::new(args.rpc_url);
     let path = Arc::new(args.path);
     let export_type = Arc::new(args.export_type);
+    let file_templates = Arc::new(file_templates);
+    let params = Arc::new(params);
+    let l2_chunks = Arc::new(Mutex::new(l2_chunks));
     // Fetch
     let semaphore = Arc::new(Semaphore::new(args.max_concurrent_chunk as usize));
     let mut handles = Vec::new();
 
     let mut chunk_id = 0;
-    for (block_chunk_start, block_chunk_end) in block_chunks {
+    for ((block_chunk_start, block_chunk_end, _, _), (block_range, chunk_datasets)) in block_chunks
+    {
+        // sampled ranges only fetch blocks aligned on the range step
+        let first_block = block_range.first_block_from(block_chunk_start);
+        if first_block > block_chunk_end {
+            continue;
+        }
         let cur_rpc_url = rpc_url.clone();
         let cur_path = path.clone();
         let cur_export_type = export_type.clone();
+        let cur_file_templates = file_templates.clone();
+        let cur_params = params.clone();
+        let cur_l2_chunks = l2_chunks.clone();
         let permit = semaphore.clone().acquire_owned().await.unwrap();
         let handle = tokio::spawn(async move {
+            let client = JsonRpcClient::new(HttpTransport::new(
+                Url::parse(cur_rpc_url.as_str()).map_err(SerpicoError::UrlParsingErr)?,
+            ));
+            // read before the data, so that a reorg while fetching is caught by the next run
+            let end_block_hash = if last_block_on_l1.is_none_or(|block| block_chunk_end > block) {
+                block_hash(&client, block_chunk_end).await?
+            } else {
+                None
+            };
             let res = fetch_data(
-                JsonRpcClient::new(HttpTransport::new(
-                    Url::parse(cur_rpc_url.as_str()).map_err(SerpicoError::UrlParsingErr)?,
-                )),
-                dataset,
-                (block_chunk_start, block_chunk_end),
+                &client,
+                &chunk_datasets,
+                (first_block, block_chunk_end),
+                block_range.step,
                 chunk_id as u16,
+                &cur_params,
             )
             .await;
-            let mut dataframe = res.unwrap().to_dataframe();
 
-            let file_name = format!(
-                "{}/{}_from_{}_to_{}.{}",
-                cur_path,
-                dataset.to_name(),
-                block_chunk_start,
-                block_chunk_end,
-                cur_export_type,
-            );
-            let mut file = File::create(file_name.as_str()).unwrap();
-            match cur_export_type.as_str() {
-                "csv" => {
-                    CsvWriter::new(&mut file).finish(&mut dataframe).unwrap();
-                }
-                "parquet" => {
-                    ParquetWriter::new(&mut file)
-                        .finish(&mut dataframe)
-                        .unwrap();
-                }
-                _ => (),
-            };
+            for (table, data) in res? {
+                let table = sampled_table(table.as_str(), block_range.step, block_range.offset());
+                let file_name =
+                    cur_file_templates[&table].render(block_chunk_start, block_chunk_end);
+                write_data(
+                    data,
+                    table.as_str(),
+                    cur_path.as_str(),
+                    file_name.as_str(),
+                    cur_export_type.as_str(),
+                    partition,
+                    column_options,
+                )?;
+                // recorded as soon as written, in case a later table or the run fails
+                let mut l2_chunks = cur_l2_chunks.lock().unwrap();
+                l2_chunks.update(
+                    table.as_str(),
+                    (block_chunk_start, block_chunk_end),
+                    end_block_hash,
+                    last_block_on_l1,
+                );
+                l2_chunks.save()?;
+            }
 
             drop(permit);
-            Ok((block_chunk_start, block_chunk_end))
+            Ok(())
         });
         handles.push(handle);
         chunk_id += 1;
     }
 
     for handle in handles {
-        let _: Result<(u64, u64), SerpicoError> = handle.await.unwrap();
+        let res = match handle.await {
+            Ok(res) => res,
+            // a panicking chunk is reported like a failing one, the others keep going
+            Err(err) => Err(SerpicoError::IoErr(io::Error::other(err))),
+        };
+        if let Err(err) = res {
+            eprintln!("Chunk failed, it will be fetched again next run: {}", err);
+        }
+    }
+    // reorgs are recorded even when no chunk was fetched
+    create_dir_all(path.as_str()).map_err(SerpicoError::IoErr)?;
+    l2_chunks.lock().unwrap().save()?;
+
+    Ok(())
+}
+
+/// Table of the chunks of a sampled block range, e.g. `blocks_every_10`, so that they are
+/// not mistaken for complete chunks of the table. Ranges not starting on a multiple of the
+/// step sample other blocks and get their own table, e.g. `blocks_every_10_offset_3`.
+fn sampled_table(table: &str, step: u64, offset: u64) -> String {
+    if step == 1 {
+        table.to_string()
+    } else if offset == 0 {
+        format!("{}_every_{}", table, step)
+    } else {
+        format!("{}_every_{}_offset_{}", table, step, offset)
+    }
+}
+
+/// Writes a chunk of a dataset table, split into partition directories if needed
+fn write_data(
+    data: Data,
+    table: &str,
+    path: &str,
+    file_name: &str,
+    export_type: &str,
+    partition: Partition,
+    column_options: ColumnOptions,
+) -> Result<(), SerpicoError> {
+    let markers_dir = format!("{}/{}/{}", path, table, CHUNK_MARKERS_DIR);
+    let parts = match partition {
+        Partition::None => vec![(path.to_string(), data)],
+        _ => {
+            // the chunk is incomplete until all its parts are written again
+            let marker = format!("{}/{}", markers_dir, file_name);
+            if Path::new(marker.as_str()).exists() {
+                remove_file(marker).map_err(SerpicoError::IoErr)?;
+            }
+            // a chunk written again may not span the same partitions anymore, e.g. after a reorg
+            remove_chunk_files(path, table, file_name, partition).map_err(SerpicoError::IoErr)?;
+            data.partition(partition)
+                .into_iter()
+                .map(|(key, data)| (format!("{}/{}/{}", path, table, key), data))
+                .collect()
+        }
+    };
+
+    for (dir, data) in parts {
+        create_dir_all(dir.as_str()).map_err(SerpicoError::IoErr)?;
+        let dataframe = data.into_dataframe(column_options);
+        write_dataframe(
+            dataframe,
+            format!("{}/{}", dir, file_name).as_str(),
+            export_type,
+        )?;
     }
 
+    if partition != Partition::None {
+        create_dir_all(markers_dir.as_str()).map_err(SerpicoError::IoErr)?;
+        File::create(format!("{}/{}", markers_dir, file_name)).map_err(SerpicoError::IoErr)?;
+    }
     Ok(())
 }
+
+/// Writes the pending block datasets to `<path>/pending`, with the capture time in a
+/// `captured_at` column and in the file names, e.g. `blocks_pending_650001_1709251200.csv`.
+/// Snapshots never match the chunk file names, so they are not taken for exported chunks.
+async fn write_pending_snapshot(
+    client: &JsonRpcClient<HttpTransport>,
+    datasets: &[Datasets],
+    params: &FetchParams,
+    path: &str,
+    export_type: &str,
+    column_options: ColumnOptions,
+) -> Result<(), SerpicoError> {
+    let captured_at = Utc::now().timestamp() as u64;
+    let (block_number, tables) = fetch_pending(client, datasets, params).await?;
+
+    let dir = format!("{}/pending", path);
+    create_dir_all(dir.as_str()).map_err(SerpicoError::IoErr)?;
+    for (table, data) in tables {
+        let mut dataframe = data.into_dataframe(column_options);
+        let height = dataframe.height();
+        dataframe
+            .with_column(Series::new("captured_at", vec![captured_at; height]))
+            .map_err(|err| SerpicoError::IoErr(io::Error::other(err)))?;
+        let file_name = format!(
+            "{}_pending_{}_{}.{}",
+            table, block_number, captured_at, export_type
+        );
+        write_dataframe(
+            dataframe,
+            format!("{}/{}", dir, file_name).as_str(),
+            export_type,
+        )?;
+    }
+    println!("Captured pending block {}", block_number);
+    Ok(())
+}
+
+fn write_dataframe(
+    mut dataframe: DataFrame,
+    file_path: &str,
+    export_type: &str,
+) -> Result<(), SerpicoError> {
+    let mut file = File::create(file_path).map_err(SerpicoError::IoErr)?;
+    let res = match export_type {
+        "csv" => {
+            stringify_list_columns(&mut dataframe);
+            CsvWriter::new(&mut file).finish(&mut dataframe)
+        }
+        "parquet" => ParquetWriter::new(&mut file)
+            .finish(&mut dataframe)
+            .map(|_| ()),
+        _ => Ok(()),
+    };
+    res.map_err(|err| SerpicoError::IoErr(io::Error::other(err)))
+}
@@ -0,0 +1,218 @@
+use crate::SerpicoError;
+use starknet::core::types::requests::GetBlockWithTxHashesRequestRef;
+use starknet::core::types::{
+    BlockId, ContractClass, EventFilter, EventsPage, Felt, FunctionCall,
+    MaybePendingBlockWithReceipts, MaybePendingBlockWithTxHashes, MaybePendingBlockWithTxs,
+    MaybePendingStateUpdate, ResourcePrice, StarknetError, TransactionReceiptWithBlockInfo,
+    TransactionTraceWithHash,
+};
+use starknet::providers::jsonrpc::{
+    HttpTransport, HttpTransportError, JsonRpcClientError, JsonRpcMethod, JsonRpcResponse,
+    JsonRpcTransport,
+};
+use starknet::providers::{JsonRpcClient, Provider, ProviderError};
+use std::future::Future;
+use std::time::Duration;
+use tokio::time::sleep;
+
+/// Calls made to a failing RPC method before giving up
+const MAX_ATTEMPTS: u32 = 8;
+
+/// JSON-RPC error code of unknown methods
+const METHOD_NOT_FOUND: i64 = -32601;
+
+/// First RPC spec version whose block headers carry the L2 gas price
+const L2_GAS_PRICE_SPEC: (u64, u64) = (0, 8);
+
+/// Calls an RPC method until the node answers, waiting twice longer after each failed call.
+///
+/// Errors answered by the node are returned right away, calling again would not change them.
+async fn with_retries<T, F, Fut>(mut request: F) -> Result<T, ProviderError>
+where
+    F: FnMut() -> Fut,
+    Fut: Future<Output = Result<T, ProviderError>>,
+{
+    let mut attempt = 0;
+    loop {
+        match request().await {
+            Err(err) if !is_answered_error(&err) && attempt + 1 < MAX_ATTEMPTS => {
+                attempt += 1;
+                sleep(Duration::from_millis(100 << attempt)).await;
+            }
+            res => return res,
+        }
+    }
+}
+
+/// Waits before checking again a state the node is still catching up on, twice longer at each
+/// attempt, and gives up with `err` once `MAX_ATTEMPTS` checks failed
+pub async fn backoff(attempt: &mut u32, err: SerpicoError) -> Result<(), SerpicoError> {
+    *attempt += 1;
+    if *attempt >= MAX_ATTEMPTS {
+        return Err(err);
+    }
+    sleep(Duration::from_millis(100 << *attempt)).await;
+    Ok(())
+}
+
+/// Whether the node answered the call with an error, rather than failing to answer
+fn is_answered_error(err: &ProviderError) -> bool {
+    match err {
+        ProviderError::StarknetError(_) => true,
+        ProviderError::Other(err) => matches!(
+            err.as_any()
+                .downcast_ref::<JsonRpcClientError<HttpTransportError>>(),
+            Some(JsonRpcClientError::JsonRpcError(_))
+        ),
+        _ => false,
+    }
+}
+
+/// Whether the node does not serve traces: it does not know the method or has no trace for the
+/// block
+pub fn is_trace_unavailable(err: &ProviderError) -> bool {
+    match err {
+        ProviderError::StarknetError(StarknetError::NoTraceAvailable(_)) => true,
+        ProviderError::Other(err) => matches!(
+            err.as_any()
+                .downcast_ref::<JsonRpcClientError<HttpTransportError>>(),
+            Some(JsonRpcClientError::JsonRpcError(err)) if err.code == METHOD_NOT_FOUND
+        ),
+        _ => false,
+    }
+}
+
+pub async fn get_block_with_tx_hashes(
+    client: &JsonRpcClient<HttpTransport>,
+    block_id: BlockId,
+) -> Result<MaybePendingBlockWithTxHashes, ProviderError> {
+    with_retries(|| client.get_block_with_tx_hashes(block_id)).await
+}
+
+/// Whether the node serves the L2 gas price of blocks, from the spec version it reports
+pub async fn serves_l2_gas_price(
+    client: &JsonRpcClient<HttpTransport>,
+) -> Result<bool, ProviderError> {
+    let version = with_retries(|| client.spec_version()).await?;
+    let mut numbers = version
+        .split('.')
+        .map(|number| number.parse::<u64>().unwrap_or(0));
+    let major = numbers.next().unwrap_or(0);
+    let minor = numbers.next().unwrap_or(0);
+    Ok((major, minor) >= L2_GAS_PRICE_SPEC)
+}
+
+/// Reads the L2 gas price of a block header, which the RPC spec 0.7 types cannot parse.
+///
+/// Calls `starknet_getBlockWithTxHashes` and only keeps the `l2_gas_price` field of the answer,
+/// `None` when the header has none.
+pub async fn get_block_l2_gas_price(
+    transport: &HttpTransport,
+    block_id: BlockId,
+) -> Result<Option<ResourcePrice>, ProviderError> {
+    with_retries(|| async {
+        let header = match transport
+            .send_request::<_, serde_json::Value>(
+                JsonRpcMethod::GetBlockWithTxHashes,
+                GetBlockWithTxHashesRequestRef {
+                    block_id: &block_id,
+                },
+            )
+            .await
+            .map_err(JsonRpcClientError::TransportError)?
+        {
+            JsonRpcResponse::Success { result, .. } => result,
+            JsonRpcResponse::Error { error, .. } => {
+                return Err(match StarknetError::try_from(&error) {
+                    Ok(error) => ProviderError::StarknetError(error),
+                    Err(_) => JsonRpcClientError::<HttpTransportError>::JsonRpcError(error).into(),
+                })
+            }
+        };
+        match header.get("l2_gas_price") {
+            Some(price) => serde_json::from_value(price.clone())
+                .map(Some)
+                .map_err(|err| JsonRpcClientError::<HttpTransportError>::JsonError(err).into()),
+            None => Ok(None),
+        }
+    })
+    .await
+}
+
+pub async fn get_block_with_txs(
+    client: &JsonRpcClient<HttpTransport>,
+    block_id: BlockId,
+) -> Result<MaybePendingBlockWithTxs, ProviderError> {
+    with_retries(|| client.get_block_with_txs(block_id)).await
+}
+
+pub async fn get_block_with_receipts(
+    client: &JsonRpcClient<HttpTransport>,
+    block_id: BlockId,
+) -> Result<MaybePendingBlockWithReceipts, ProviderError> {
+    with_retries(|| client.get_block_with_receipts(block_id)).await
+}
+
+pub async fn get_transaction_receipt(
+    client: &JsonRpcClient<HttpTransport>,
+    transaction_hash: Felt,
+) -> Result<TransactionReceiptWithBlockInfo, ProviderError> {
+    with_retries(|| client.get_transaction_receipt(transaction_hash)).await
+}
+
+pub async fn trace_block_transactions(
+    client: &JsonRpcClient<HttpTransport>,
+    block_id: BlockId,
+) -> Result<Vec<TransactionTraceWithHash>, ProviderError> {
+    with_retries(|| client.trace_block_transactions(block_id)).await
+}
+
+pub async fn get_events(
+    client: &JsonRpcClient<HttpTransport>,
+    filter: &EventFilter,
+    continuation_token: Option<&String>,
+    chunk_size: u64,
+) -> Result<EventsPage, ProviderError> {
+    with_retries(|| client.get_events(filter.clone(), continuation_token.cloned(), chunk_size))
+        .await
+}
+
+pub async fn get_state_update(
+    client: &JsonRpcClient<HttpTransport>,
+    block_id: BlockId,
+) -> Result<MaybePendingStateUpdate, ProviderError> {
+    with_retries(|| client.get_state_update(block_id)).await
+}
+
+pub async fn get_class(
+    client: &JsonRpcClient<HttpTransport>,
+    block_id: BlockId,
+    class_hash: Felt,
+) -> Result<ContractClass, ProviderError> {
+    with_retries(|| client.get_class(block_id, class_hash)).await
+}
+
+pub async fn call(
+    client: &JsonRpcClient<HttpTransport>,
+    request: &FunctionCall,
+    block_id: BlockId,
+) -> Result<Vec<Felt>, ProviderError> {
+    with_retries(|| client.call(request, block_id)).await
+}
+
+pub async fn get_storage_at(
+    client: &JsonRpcClient<HttpTransport>,
+    contract: Felt,
+    key: Felt,
+    block_id: BlockId,
+) -> Result<Felt, ProviderError> {
+    with_retries(|| client.get_storage_at(contract, key, block_id)).await
+}
+
+pub async fn get_nonce(
+    client: &JsonRpcClient<HttpTransport>,
+    block_id: BlockId,
+    account: Felt,
+) -> Result<Felt, ProviderError> {
+    with_retries(|| client.get_nonce(block_id, account)).await
+}
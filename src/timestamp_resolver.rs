@@ -0,0 +1,141 @@
+use crate::cli_parser::{check_block_ranges, BlockRange, DEFAULT_START_BLOCK};
+use crate::rpc::get_block_with_tx_hashes;
+use crate::SerpicoError;
+use starknet::core::types::{BlockId, MaybePendingBlockWithTxHashes};
+use starknet::providers::jsonrpc::HttpTransport;
+use starknet::providers::JsonRpcClient;
+use std::collections::BTreeMap;
+use std::path::Path;
+
+/// Resolves timestamp intervals (end excluded) into block ranges.
+///
+/// The first block at or after each timestamp is found by binary search on block
+/// timestamps, and resolutions are cached in `cache_file` for later runs.
+pub async fn resolve_timestamps(
+    client: &JsonRpcClient<HttpTransport>,
+    timestamp_ranges: Vec<(Option<u64>, Option<u64>)>,
+    last_block: u64,
+    cache_file: String,
+) -> Result<Vec<BlockRange>, SerpicoError> {
+    let mut cache = load_cache(&cache_file)?;
+
+    let mut ranges = Vec::new();
+    for (timestamp_start, timestamp_end) in timestamp_ranges {
+        let block_start = match timestamp_start {
+            None => DEFAULT_START_BLOCK,
+            Some(timestamp) => first_block_at(client, timestamp, last_block, &mut cache)
+                .await?
+                .ok_or_else(|| {
+                    SerpicoError::DateParsingErr(format!(
+                        "no block at or after timestamp {}",
+                        timestamp
+                    ))
+                })?,
+        };
+        let block_end = match timestamp_end {
+            None => last_block,
+            Some(timestamp) => {
+                match first_block_at(client, timestamp, last_block, &mut cache).await? {
+                    Some(block) => block.saturating_sub(1),
+                    None => last_block,
+                }
+            }
+        };
+
+        if block_start > block_end {
+            return Err(SerpicoError::DateParsingErr(format!(
+                "no block between timestamps {:?} and {:?}",
+                timestamp_start, timestamp_end
+            )));
+        }
+        println!(
+            "Timestamps {:?} to {:?} resolved to blocks {} to {}",
+            timestamp_start, timestamp_end, block_start, block_end
+        );
+        ranges.push(BlockRange {
+            start: block_start,
+            end: block_end,
+            step: 1,
+        });
+    }
+
+    save_cache(&cache_file, &cache)?;
+    check_block_ranges(&mut ranges)?;
+    Ok(ranges)
+}
+
+/// First block whose timestamp is greater or equal to `timestamp`, if already produced
+async fn first_block_at(
+    client: &JsonRpcClient<HttpTransport>,
+    timestamp: u64,
+    last_block: u64,
+    cache: &mut BTreeMap<u64, u64>,
+) -> Result<Option<u64>, SerpicoError> {
+    if let Some(block) = cache.get(&timestamp) {
+        return Ok(Some(*block));
+    }
+
+    let mut low = 0;
+    let mut high = last_block + 1;
+    while low < high {
+        let middle = low + (high - low) / 2;
+        if block_timestamp(client, middle).await? >= timestamp {
+            high = middle;
+        } else {
+            low = middle + 1;
+        }
+    }
+
+    if low > last_block {
+        // the block does not exist yet, resolving it later may give another result
+        return Ok(None);
+    }
+    cache.insert(timestamp, low);
+    Ok(Some(low))
+}
+
+async fn block_timestamp(
+    client: &JsonRpcClient<HttpTransport>,
+    block: u64,
+) -> Result<u64, SerpicoError> {
+    match get_block_with_tx_hashes(client, BlockId::Number(block))
+        .await
+        .map_err(SerpicoError::ClientErr)?
+    {
+        MaybePendingBlockWithTxHashes::Block(b) => Ok(b.timestamp),
+        MaybePendingBlockWithTxHashes::PendingBlock(b) => Ok(b.timestamp),
+    }
+}
+
+fn load_cache(cache_file: &str) -> Result<BTreeMap<u64, u64>, SerpicoError> {
+    let mut cache = BTreeMap::new();
+    if !Path::new(cache_file).exists() {
+        return Ok(cache);
+    }
+
+    let mut reader = csv::Reader::from_path(cache_file).map_err(SerpicoError::WriterErr)?;
+    for record in reader.records() {
+        let record = record.map_err(SerpicoError::WriterErr)?;
+        let timestamp = record[0]
+            .parse::<u64>()
+            .map_err(SerpicoError::IntParsingErr)?;
+        let block = record[1]
+            .parse::<u64>()
+            .map_err(SerpicoError::IntParsingErr)?;
+        cache.insert(timestamp, block);
+    }
+    Ok(cache)
+}
+
+fn save_cache(cache_file: &str, cache: &BTreeMap<u64, u64>) -> Result<(), SerpicoError> {
+    let mut writer = csv::Writer::from_path(cache_file).map_err(SerpicoError::WriterErr)?;
+    writer
+        .write_record(["timestamp", "block_number"])
+        .map_err(SerpicoError::WriterErr)?;
+    for (timestamp, block) in cache {
+        writer
+            .write_record([timestamp.to_string(), block.to_string()])
+            .map_err(SerpicoError::WriterErr)?;
+    }
+    writer.flush().map_err(SerpicoError::IoErr)
+}
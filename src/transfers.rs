@@ -0,0 +1,290 @@
+use crate::abi::u256_to_decimal;
+use crate::{BlockEvent, Erc20Transfer, NftTransfer};
+use starknet::core::types::Felt;
+use starknet::core::utils::starknet_keccak;
+use std::collections::HashSet;
+
+/// Selector of the `Transfer` event, shared by ERC-20 and ERC-721
+pub fn transfer_selector() -> Felt {
+    starknet_keccak(b"Transfer")
+}
+
+/// Selector of the ERC-1155 `TransferSingle` event
+pub fn transfer_single_selector() -> Felt {
+    starknet_keccak(b"TransferSingle")
+}
+
+/// Selector of the ERC-1155 `TransferBatch` event
+pub fn transfer_batch_selector() -> Felt {
+    starknet_keccak(b"TransferBatch")
+}
+
+/// Tokens emitting `Transfer` events with every member in data, the layout shared by Cairo 0
+/// ERC-20 and ERC-721 tokens
+pub fn data_layout_tokens(logs: &[BlockEvent]) -> HashSet<Felt> {
+    let selector = transfer_selector();
+    logs.iter()
+        .filter(|log| {
+            matches!(
+                (&log.event.keys[..], &log.event.data[..]),
+                ([key], [_, _, _, _]) if *key == selector
+            )
+        })
+        .map(|log| log.event.from_address)
+        .collect()
+}
+
+/// Decodes ERC-20 `Transfer` events.
+///
+/// Cairo 0 tokens put every member in data (`from, to, amount.low, amount.high`), Cairo 1 tokens
+/// key `from` and `to` and leave the amount in data. Cairo 0 ERC-721 transfers share the first
+/// layout, so the events of `nft_tokens` are left out.
+pub fn to_erc20_transfers(logs: &[BlockEvent], nft_tokens: &HashSet<Felt>) -> Vec<Erc20Transfer> {
+    let selector = transfer_selector();
+    logs.iter()
+        .filter_map(|log| {
+            let (from, to, low, high) = match (&log.event.keys[..], &log.event.data[..]) {
+                ([key], [from, to, low, high])
+                    if *key == selector && !nft_tokens.contains(&log.event.from_address) =>
+                {
+                    (from, to, low, high)
+                }
+                ([key, from, to], [low, high]) if *key == selector => (from, to, low, high),
+                _ => return None,
+            };
+            Some(Erc20Transfer {
+                log: log.clone(),
+                from: *from,
+                to: *to,
+                amount: u256_to_decimal(*low, *high),
+            })
+        })
+        .collect()
+}
+
+/// Decodes ERC-721 `Transfer` events. Cairo 1 tokens key every member including the `u256`
+/// token id.
+///
+/// Cairo 0 tokens put every member in data, exactly like Cairo 0 ERC-20 transfers, so only the
+/// events of `nft_tokens` are decoded with that layout.
+pub fn to_erc721_transfers(logs: &[BlockEvent], nft_tokens: &HashSet<Felt>) -> Vec<NftTransfer> {
+    let selector = transfer_selector();
+    logs.iter()
+        .filter_map(|log| {
+            let (from, to, low, high) = match (&log.event.keys[..], &log.event.data[..]) {
+                ([key, from, to, low, high], []) if *key == selector => (from, to, low, high),
+                ([key], [from, to, low, high])
+                    if *key == selector && nft_tokens.contains(&log.event.from_address) =>
+                {
+                    (from, to, low, high)
+                }
+                _ => return None,
+            };
+            Some(NftTransfer {
+                log: log.clone(),
+                operator: None,
+                from: *from,
+                to: *to,
+                token_id: u256_to_decimal(*low, *high),
+                amount: None,
+                batch_index: 0,
+            })
+        })
+        .collect()
+}
+
+/// Decodes ERC-1155 `TransferSingle` and `TransferBatch` events, one row per token id.
+///
+/// Cairo 0 tokens put every member in data, Cairo 1 tokens key `operator`, `from` and `to`.
+pub fn to_erc1155_transfers(logs: &[BlockEvent]) -> Vec<NftTransfer> {
+    let single_selector = transfer_single_selector();
+    let batch_selector = transfer_batch_selector();
+    let mut transfers = Vec::new();
+    for log in logs {
+        let (selector, operator, from, to, values) =
+            match (&log.event.keys[..], &log.event.data[..]) {
+                ([selector], [operator, from, to, values @ ..]) => {
+                    (selector, operator, from, to, values)
+                }
+                ([selector, operator, from, to], values) => (selector, operator, from, to, values),
+                _ => continue,
+            };
+
+        let ids_amounts = if *selector == single_selector {
+            match values {
+                [id_low, id_high, amount_low, amount_high] => vec![(
+                    u256_to_decimal(*id_low, *id_high),
+                    u256_to_decimal(*amount_low, *amount_high),
+                )],
+                _ => continue,
+            }
+        } else if *selector == batch_selector {
+            match decode_batch(values) {
+                Some(ids_amounts) => ids_amounts,
+                None => continue,
+            }
+        } else {
+            continue;
+        };
+
+        for (batch_index, (token_id, amount)) in ids_amounts.into_iter().enumerate() {
+            transfers.push(NftTransfer {
+                log: log.clone(),
+                operator: Some(*operator),
+                from: *from,
+                to: *to,
+                token_id,
+                amount: Some(amount),
+                batch_index: batch_index as u64,
+            });
+        }
+    }
+    transfers
+}
+
+/// Token ids and amounts of a `TransferBatch`, two `u256` arrays of the same length
+fn decode_batch(values: &[Felt]) -> Option<Vec<(String, String)>> {
+    let (ids, rest) = decode_u256_array(values)?;
+    let (amounts, rest) = decode_u256_array(rest)?;
+    if !rest.is_empty() || ids.len() != amounts.len() {
+        return None;
+    }
+    Some(ids.into_iter().zip(amounts).collect())
+}
+
+/// `u256` array preceded by its length, with the felts following it
+fn decode_u256_array(values: &[Felt]) -> Option<(Vec<String>, &[Felt])> {
+    let (len, rest) = values.split_first()?;
+    let len = u64::try_from(*len).ok()?;
+    if len > rest.len() as u64 / 2 {
+        return None;
+    }
+    let (array, rest) = rest.split_at(len as usize * 2);
+    Some((
+        array
+            .chunks(2)
+            .map(|u256| u256_to_decimal(u256[0], u256[1]))
+            .collect(),
+        rest,
+    ))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use starknet::core::types::EmittedEvent;
+
+    const TOKEN: Felt = Felt::from_hex_unchecked("0x1234");
+    const NFT: Felt = Felt::from_hex_unchecked("0x5678");
+
+    fn log(from_address: Felt, keys: Vec<Felt>, data: Vec<Felt>) -> BlockEvent {
+        BlockEvent {
+            event: EmittedEvent {
+                from_address,
+                keys,
+                data,
+                block_hash: Some(Felt::ZERO),
+                block_number: Some(1),
+                transaction_hash: Felt::ONE,
+            },
+            timestamp: 0,
+            transaction_index: Some(0),
+            event_index: 0,
+            transaction_event_index: 0,
+        }
+    }
+
+    fn felts(values: &[u64]) -> Vec<Felt> {
+        values.iter().copied().map(Felt::from).collect()
+    }
+
+    #[test]
+    fn decodes_both_erc20_layouts() {
+        let logs = [
+            // Cairo 0: every member in data
+            log(TOKEN, vec![transfer_selector()], felts(&[1, 2, 5, 0])),
+            // Cairo 1: from and to in keys
+            log(
+                TOKEN,
+                vec![transfer_selector(), Felt::from(3u64), Felt::from(4u64)],
+                felts(&[0, 1]),
+            ),
+        ];
+        let transfers = to_erc20_transfers(&logs, &HashSet::new());
+        assert_eq!(
+            transfers
+                .iter()
+                .map(|transfer| (transfer.from, transfer.to, transfer.amount.as_str()))
+                .collect::<Vec<_>>(),
+            vec![
+                (Felt::from(1u64), Felt::from(2u64), "5"),
+                (
+                    Felt::from(3u64),
+                    Felt::from(4u64),
+                    "340282366920938463463374607431768211456"
+                ),
+            ]
+        );
+        assert_eq!(data_layout_tokens(&logs), HashSet::from([TOKEN]));
+    }
+
+    #[test]
+    fn routes_cairo_0_nft_transfers_to_erc721() {
+        let logs = [
+            log(TOKEN, vec![transfer_selector()], felts(&[1, 2, 5, 0])),
+            log(NFT, vec![transfer_selector()], felts(&[1, 2, 7, 0])),
+        ];
+        let nft_tokens = HashSet::from([NFT]);
+        let erc20_transfers = to_erc20_transfers(&logs, &nft_tokens);
+        assert_eq!(erc20_transfers.len(), 1);
+        assert_eq!(erc20_transfers[0].log.event.from_address, TOKEN);
+        let erc721_transfers = to_erc721_transfers(&logs, &nft_tokens);
+        assert_eq!(erc721_transfers.len(), 1);
+        assert_eq!(erc721_transfers[0].log.event.from_address, NFT);
+        assert_eq!(erc721_transfers[0].token_id, "7");
+    }
+
+    #[test]
+    fn decodes_keyed_erc721_token_ids() {
+        let logs = [log(
+            NFT,
+            [vec![transfer_selector()], felts(&[1, 2, 9, 1])].concat(),
+            vec![],
+        )];
+        let transfers = to_erc721_transfers(&logs, &HashSet::new());
+        assert_eq!(transfers.len(), 1);
+        assert_eq!(
+            transfers[0].token_id,
+            "340282366920938463463374607431768211465"
+        );
+        assert_eq!(
+            (transfers[0].operator, transfers[0].amount.as_ref()),
+            (None, None)
+        );
+        // keyed ERC-721 transfers are no ERC-20 ones
+        assert!(to_erc20_transfers(&logs, &HashSet::new()).is_empty());
+    }
+
+    #[test]
+    fn decodes_batches() {
+        assert_eq!(
+            decode_batch(&felts(&[2, 1, 0, 2, 0, 2, 10, 0, 20, 0])),
+            Some(vec![
+                ("1".to_string(), "10".to_string()),
+                ("2".to_string(), "20".to_string())
+            ])
+        );
+    }
+
+    #[test]
+    fn rejects_batches_with_mismatched_lengths() {
+        assert_eq!(decode_batch(&felts(&[2, 1, 0, 2, 0, 1, 10, 0])), None);
+    }
+
+    #[test]
+    fn rejects_truncated_batches() {
+        assert_eq!(decode_batch(&felts(&[2, 1, 0, 2, 0, 2, 10, 0, 20])), None);
+        assert_eq!(decode_batch(&felts(&[3, 1, 0])), None);
+        assert_eq!(decode_batch(&[]), None);
+    }
+}
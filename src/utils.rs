@@ -1,16 +1,22 @@
-use std::collections::HashSet;
+use crate::cli_parser::Partition;
+use crate::file_template::FileTemplate;
+use starknet::core::types::Felt;
+use starknet::core::utils::starknet_keccak;
+use std::collections::BTreeMap;
+use std::fs::{read_dir, remove_file};
+use std::path::Path;
 
 pub fn split_block_chunks(
     block_start: u64,
     block_end: u64,
     chunk_size: u64,
-    seen_chunks: &mut Vec<(u64, u64)>,
+    seen_chunks: &mut [(u64, u64)],
 ) -> Vec<(u64, u64)> {
     seen_chunks.sort_by(|a, b| {
         if a.0 != b.0 {
             return a.0.cmp(&b.0);
         }
-        return b.1.cmp(&a.1);
+        b.1.cmp(&a.1)
     });
     let mut seen_chunk_id = 0;
 
@@ -19,20 +25,20 @@ pub fn split_block_chunks(
     for k in (block_start / chunk_size)..(block_end / chunk_size + 1) {
         let left_side = u64::max(block_start, chunk_size * k);
 
-        while (seen_chunk_id < seen_chunks.len() && seen_chunks[seen_chunk_id].0 < left_side) {
+        while seen_chunk_id < seen_chunks.len() && seen_chunks[seen_chunk_id].0 < left_side {
             seen_chunk_id += 1;
         }
         let right_side = u64::min(chunk_size * k + chunk_size - 1, block_end);
 
         if seen_chunk_id < seen_chunks.len() && seen_chunks[seen_chunk_id].0 == left_side {
             // if left side matches, try to extend as much as possible to match current segment
-            while (seen_chunk_id + 1 < seen_chunks.len()
+            while seen_chunk_id + 1 < seen_chunks.len()
                 && seen_chunks[seen_chunk_id].1 + 1 == seen_chunks[seen_chunk_id + 1].0
-                && seen_chunks[seen_chunk_id + 1].0 <= right_side)
+                && seen_chunks[seen_chunk_id + 1].0 <= right_side
             {
                 seen_chunk_id += 1;
             }
-            if right_side >= seen_chunks[seen_chunk_id].1 + 1 {
+            if right_side > seen_chunks[seen_chunk_id].1 {
                 block_segments.push((seen_chunks[seen_chunk_id].1 + 1, right_side));
             }
         } else {
@@ -42,3 +48,178 @@ pub fn split_block_chunks(
 
     block_segments
 }
+
+/// Directory of a partitioned dataset holding an empty file per complete chunk, named like
+/// the chunk files
+pub const CHUNK_MARKERS_DIR: &str = "_chunks";
+
+/// Lists the block chunks already exported for a dataset. When output is partitioned, a chunk
+/// may have no rows or be split across partition directories, so its marker
+/// (`{path}/{dataset}/_chunks/...`) is looked for instead of its files.
+pub fn scan_chunks(
+    path: &str,
+    dataset_name: &str,
+    file_template: &FileTemplate,
+    partition: Partition,
+) -> Vec<(u64, u64)> {
+    let mut chunks_seen = Vec::new();
+
+    if partition == Partition::None {
+        scan_chunk_dir(Path::new(path), file_template, &mut chunks_seen);
+    } else {
+        let markers_dir = Path::new(path).join(dataset_name).join(CHUNK_MARKERS_DIR);
+        scan_chunk_dir(&markers_dir, file_template, &mut chunks_seen);
+    }
+
+    chunks_seen.sort();
+    chunks_seen
+}
+
+/// Removes the files of a chunk from every partition directory of a dataset
+pub fn remove_chunk_files(
+    path: &str,
+    dataset_name: &str,
+    file_name: &str,
+    partition: Partition,
+) -> std::io::Result<()> {
+    if let Ok(entries) = read_dir(Path::new(path).join(dataset_name)) {
+        for entry in entries.flatten() {
+            let is_partition = entry
+                .file_name()
+                .to_str()
+                .is_some_and(|dir_name| partition.matches(dir_name));
+            let file = entry.path().join(file_name);
+            if is_partition && file.is_file() {
+                remove_file(file)?;
+            }
+        }
+    }
+    Ok(())
+}
+
+fn scan_chunk_dir(dir: &Path, file_template: &FileTemplate, chunks_seen: &mut Vec<(u64, u64)>) {
+    if let Ok(entries) = read_dir(dir) {
+        for entry in entries.flatten() {
+            if let Some(chunk) = entry
+                .file_name()
+                .to_str()
+                .and_then(|file_name| file_template.parse(file_name))
+            {
+                chunks_seen.push(chunk);
+            }
+        }
+    }
+}
+
+/// Short hash of a list of felts, e.g. to name the files of a set of call targets
+pub fn fingerprint(felts: &[Felt]) -> String {
+    let bytes: Vec<u8> = felts.iter().flat_map(|felt| felt.to_bytes_be()).collect();
+    let hash = format!("{:064x}", starknet_keccak(&bytes));
+    hash[hash.len() - 8..].to_string()
+}
+
+/// Groups rows by key, keeping the row order inside each group
+pub fn group_by<T>(rows: Vec<T>, key: impl Fn(&T) -> String) -> BTreeMap<String, Vec<T>> {
+    let mut groups = BTreeMap::new();
+    for row in rows {
+        groups.entry(key(&row)).or_insert_with(Vec::new).push(row);
+    }
+    groups
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::file_template::DEFAULT_FILE_TEMPLATE;
+    use std::fs::{create_dir_all, remove_dir_all, File};
+    use std::path::PathBuf;
+
+    fn output_dir(name: &str) -> PathBuf {
+        let dir = std::env::temp_dir().join(format!("serpico_chunks_{}", name));
+        let _ = remove_dir_all(&dir);
+        dir
+    }
+
+    fn touch(dir: &Path, file_name: &str) {
+        create_dir_all(dir).unwrap();
+        File::create(dir.join(file_name)).unwrap();
+    }
+
+    fn template() -> FileTemplate {
+        FileTemplate::new(DEFAULT_FILE_TEMPLATE, "blocks", "mainnet", "csv", "").unwrap()
+    }
+
+    #[test]
+    fn scans_partitioned_chunks_by_marker() {
+        let dir = output_dir("markers");
+        let table_dir = dir.join("blocks");
+        let file_template = template();
+        // an empty chunk has a marker and no partition file
+        touch(
+            &table_dir.join(CHUNK_MARKERS_DIR),
+            &file_template.render(0, 99),
+        );
+        // an interrupted chunk has partition files and no marker yet
+        touch(
+            &table_dir.join("block_bucket=100"),
+            &file_template.render(100, 199),
+        );
+        // a complete chunk has both
+        touch(
+            &table_dir.join("block_bucket=200"),
+            &file_template.render(200, 299),
+        );
+        touch(
+            &table_dir.join(CHUNK_MARKERS_DIR),
+            &file_template.render(200, 299),
+        );
+
+        let path = dir.to_str().unwrap();
+        assert_eq!(
+            scan_chunks(path, "blocks", &file_template, Partition::BlockBucket(100)),
+            vec![(0, 99), (200, 299)]
+        );
+        // unpartitioned tables are scanned by file, markers are not looked at
+        assert!(scan_chunks(path, "blocks", &file_template, Partition::None).is_empty());
+        remove_dir_all(&dir).unwrap();
+    }
+
+    #[test]
+    fn removes_chunk_files_of_every_partition() {
+        let dir = output_dir("repartition");
+        let table_dir = dir.join("blocks");
+        let file_template = template();
+        let chunk = file_template.render(50, 149);
+        let other_chunk = file_template.render(150, 249);
+        // the chunk spanned two buckets before a reorg
+        touch(&table_dir.join("block_bucket=0"), &chunk);
+        touch(&table_dir.join("block_bucket=100"), &chunk);
+        touch(&table_dir.join("block_bucket=100"), &other_chunk);
+        touch(&table_dir.join(CHUNK_MARKERS_DIR), &chunk);
+
+        remove_chunk_files(
+            dir.to_str().unwrap(),
+            "blocks",
+            &chunk,
+            Partition::BlockBucket(100),
+        )
+        .unwrap();
+        assert!(!table_dir.join("block_bucket=0").join(&chunk).exists());
+        assert!(!table_dir.join("block_bucket=100").join(&chunk).exists());
+        assert!(table_dir
+            .join("block_bucket=100")
+            .join(&other_chunk)
+            .exists());
+        // markers are not partitions, write_data handles them
+        assert!(table_dir.join(CHUNK_MARKERS_DIR).join(&chunk).exists());
+        // missing tables have nothing to remove
+        remove_chunk_files(
+            dir.to_str().unwrap(),
+            "transactions",
+            &chunk,
+            Partition::Date,
+        )
+        .unwrap();
+        remove_dir_all(&dir).unwrap();
+    }
+}